@@ -0,0 +1,270 @@
+use bevy_ecs::{
+    change_detection::DetectChanges,
+    resource::Resource,
+    system::{Commands, Res, ResMut},
+};
+use glam::Vec3;
+use gpu_bytes::{AsStd140, AsStd430};
+use gpu_bytes_derive::{AsStd140, AsStd430};
+use wgpu::util::DeviceExt;
+
+use crate::app::{
+    menu::Menu,
+    renderer::{
+        atmosphere_lut::{
+            LUT_WAVELENGTH_COUNT, MULTIPLE_SCATTERING_LUT_SIZE, SKY_VIEW_LUT_SIZE,
+            TRANSMITTANCE_LUT_SIZE,
+        },
+        SurfaceState,
+    },
+    spectral_data::SPECTRAL_ATLAS_WIDTH,
+};
+
+use super::{
+    composition::{Compositions, DENSITY_PROFILE_RESOLUTION},
+    environment::EnvironmentMaps,
+    Atmosphere,
+};
+
+#[derive(Resource)]
+pub struct AtmosphereBinding {
+    pub compositions: Compositions,
+
+    // Light the scene instead of the atmosphere, and are in a bind group of their own
+    pub environment_maps: EnvironmentMaps,
+
+    pub atmosphere_buffer: wgpu::Buffer,
+
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl AtmosphereBinding {
+    pub fn init(mut commands: Commands, surface_state: Res<SurfaceState>) {
+        let gpu = &surface_state.gpu;
+
+        let compositions = Compositions::load();
+        let environment_maps = EnvironmentMaps::load(&gpu.device, &gpu.queue);
+
+        let atmosphere_uniform = AtmosphereUniform::new(&Atmosphere::default(), &compositions);
+
+        let atmosphere_buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("atmosphere_buffer"),
+                contents: atmosphere_uniform.as_std140().as_slice(),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let constituents: Vec<AtmosphereConstituent> = compositions
+            .constituents()
+            .map(|constituent| {
+                constituent.map_or_else(Default::default, |constituent| AtmosphereConstituent {
+                    phase_function: constituent.phase_function.ty(),
+                    anisotropy: constituent.phase_function.anisotropy(),
+                    max_altitude: constituent.max_altitude,
+                    max_number_density: constituent.max_number_density(),
+                })
+            })
+            .collect();
+
+        let constituents_buffer =
+            gpu.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("constituents_buffer"),
+                    contents: constituents.as_std430().as_slice(),
+                    usage: wgpu::BufferUsages::STORAGE,
+                });
+
+        let create_texture =
+            |label: &str, width: u32, format: wgpu::TextureFormat, texels: &[f32]| {
+                gpu.device.create_texture_with_data(
+                    &gpu.queue,
+                    &wgpu::TextureDescriptor {
+                        label: Some(label),
+                        size: wgpu::Extent3d {
+                            width,
+                            height: constituents.len() as u32,
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format,
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                        view_formats: &[],
+                    },
+                    wgpu::util::TextureDataOrder::LayerMajor,
+                    bytemuck::cast_slice(texels),
+                )
+            };
+
+        let cross_sections_texture = create_texture(
+            "cross_sections_texture",
+            SPECTRAL_ATLAS_WIDTH,
+            wgpu::TextureFormat::Rg32Float,
+            &compositions.cross_section_texels(),
+        );
+
+        let number_densities_texture = create_texture(
+            "number_densities_texture",
+            DENSITY_PROFILE_RESOLUTION,
+            wgpu::TextureFormat::R32Float,
+            &compositions.number_density_texels(),
+        );
+
+        // Written by the lookup table passes, and read by them and the material pass
+        let create_lut = |label: &str, (width, height): (u32, u32)| {
+            let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: LUT_WAVELENGTH_COUNT,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R32Float,
+                usage: wgpu::TextureUsages::STORAGE_BINDING,
+                view_formats: &[],
+            });
+
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            })
+        };
+
+        let lut_views = [
+            create_lut("transmittance_lut_texture", TRANSMITTANCE_LUT_SIZE),
+            create_lut(
+                "multiple_scattering_lut_texture",
+                MULTIPLE_SCATTERING_LUT_SIZE,
+            ),
+            create_lut("sky_view_lut_texture", SKY_VIEW_LUT_SIZE),
+        ];
+
+        let cross_sections_view = cross_sections_texture.create_view(&Default::default());
+        let number_densities_view = number_densities_texture.create_view(&Default::default());
+
+        let mut entries = vec![
+            wgputil::binding::bind_buffer_uniform(&atmosphere_buffer),
+            wgputil::binding::bind_buffer_storage(&constituents_buffer, true),
+            wgputil::binding::bind_texture(
+                &cross_sections_view,
+                wgputil::texture::sample_type(&gpu.device, &cross_sections_texture).unwrap(),
+                wgpu::TextureViewDimension::D2,
+            ),
+            wgputil::binding::bind_texture(
+                &number_densities_view,
+                wgputil::texture::sample_type(&gpu.device, &number_densities_texture).unwrap(),
+                wgpu::TextureViewDimension::D2,
+            ),
+        ];
+
+        entries.extend(lut_views.iter().map(|view| {
+            wgputil::binding::bind_storage_texture(
+                view,
+                wgpu::TextureFormat::R32Float,
+                wgpu::TextureViewDimension::D2Array,
+                wgpu::StorageTextureAccess::ReadWrite,
+            )
+        }));
+
+        let (bind_group_layout, bind_group) =
+            wgputil::binding::create_sequential_linked(&gpu.device, "atmosphere_binding", &entries);
+
+        commands.insert_resource(Self {
+            compositions,
+            environment_maps,
+            atmosphere_buffer,
+            bind_group_layout,
+            bind_group,
+        });
+    }
+
+    pub fn update(
+        surface_state: Res<SurfaceState>,
+        mut atmosphere_binding: ResMut<AtmosphereBinding>,
+        atmosphere: Res<Atmosphere>,
+        menu: Res<Menu>,
+    ) {
+        atmosphere_binding.environment_maps.select(
+            &surface_state.gpu.device,
+            &surface_state.gpu.queue,
+            menu.settings.pathtrace.environment_map,
+        );
+
+        if !atmosphere.is_changed() {
+            return;
+        }
+
+        wgputil::buffer::write_slice(
+            &surface_state.gpu.queue,
+            &atmosphere_binding.atmosphere_buffer,
+            AtmosphereUniform::new(&atmosphere, &atmosphere_binding.compositions)
+                .as_std140()
+                .as_slice(),
+            0,
+        );
+    }
+}
+
+// Matches `Atmosphere` in atmosphere.slang
+#[derive(AsStd140, Default)]
+pub struct AtmosphereUniform {
+    planet_position: Vec3,
+    planet_radius: f32,
+    atmosphere_radius: f32,
+
+    ground_albedo: f32,
+
+    rayleigh_scale_height: f32,
+    mie_scale_height: f32,
+
+    mie_anisotropy: f32,
+    turbidity: f32,
+
+    ozone_peak_altitude: f32,
+    ozone_width: f32,
+    ozone_amount: f32,
+
+    // Constituents of the selected composition, none selects the built-in model
+    constituent_start: u32,
+    constituent_count: u32,
+}
+
+impl AtmosphereUniform {
+    fn new(atmosphere: &Atmosphere, compositions: &Compositions) -> Self {
+        let (constituent_start, constituent_count) =
+            atmosphere.composition.map_or((0, 0), |composition| {
+                compositions.constituent_range(composition)
+            });
+
+        Self {
+            planet_position: atmosphere.planet_position(),
+            planet_radius: atmosphere.planet_radius,
+            atmosphere_radius: atmosphere.planet_radius + atmosphere.atmosphere_height,
+            ground_albedo: atmosphere.ground_albedo,
+            rayleigh_scale_height: atmosphere.rayleigh_scale_height,
+            mie_scale_height: atmosphere.mie_scale_height,
+            mie_anisotropy: atmosphere.mie_anisotropy,
+            turbidity: atmosphere.turbidity,
+            ozone_peak_altitude: atmosphere.ozone_peak_altitude,
+            ozone_width: atmosphere.ozone_width,
+            ozone_amount: atmosphere.ozone_amount,
+            constituent_start,
+            constituent_count,
+        }
+    }
+}
+
+// Matches `AtmosphereConstituent` in atmosphere.slang
+#[derive(AsStd430, Default, Clone, Copy)]
+pub struct AtmosphereConstituent {
+    phase_function: u32,
+    anisotropy: f32,
+    max_altitude: f32,
+    max_number_density: f32,
+}
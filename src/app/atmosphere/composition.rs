@@ -0,0 +1,264 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    app::spectral_data::{self, Table, SPECTRAL_ATLAS_START, SPECTRAL_ATLAS_WIDTH},
+    util,
+};
+
+// Number of samples of each density profile, spread evenly from the ground to the highest tabulated altitude
+pub const DENSITY_PROFILE_RESOLUTION: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PhaseFunction {
+    Isotropic,
+    Rayleigh,
+    // Asymmetry parameter g, positive values scatter forward
+    HenyeyGreenstein(f32),
+}
+
+impl PhaseFunction {
+    // Parses a phase.txt file, which is one of `isotropic`, `rayleigh` or `henyey-greenstein <g>`
+    fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+
+        let phase_function = match words.next()? {
+            "isotropic" => PhaseFunction::Isotropic,
+            "rayleigh" => PhaseFunction::Rayleigh,
+            "henyey-greenstein" => {
+                let g: f32 = words.next()?.parse().ok()?;
+
+                if !(-1.0 < g && g < 1.0) {
+                    return None;
+                }
+
+                PhaseFunction::HenyeyGreenstein(g)
+            }
+            _ => return None,
+        };
+
+        words.next().is_none().then_some(phase_function)
+    }
+
+    // Matches `PhaseFunction` in atmosphere.slang
+    pub fn ty(self) -> u32 {
+        match self {
+            PhaseFunction::Isotropic => 0,
+            PhaseFunction::Rayleigh => 1,
+            PhaseFunction::HenyeyGreenstein(_) => 2,
+        }
+    }
+
+    pub fn anisotropy(self) -> f32 {
+        match self {
+            PhaseFunction::HenyeyGreenstein(g) => g,
+            _ => 0.0,
+        }
+    }
+}
+
+// A gas or a kind of particle in an atmosphere. Loaded from a directory holding:
+// - cross_section.csv: wavelength in nm, scattering and absorption cross sections in m^2
+// - density.csv: altitude in m, number density in m^-3
+// - phase.txt: the phase function of its scattering
+pub struct Constituent {
+    // Directory name
+    pub name: String,
+    pub phase_function: PhaseFunction,
+
+    // Resampled to the spectral atlas wavelengths
+    pub cross_sections: Vec<[f32; 2]>,
+
+    // Resampled to `DENSITY_PROFILE_RESOLUTION` altitudes from the ground to `max_altitude`, higher altitudes use the
+    // last sample
+    pub number_densities: Vec<f32>,
+    pub max_altitude: f32,
+}
+
+impl Constituent {
+    fn load(directory: &Path) -> io::Result<Self> {
+        let phase_path = directory.join("phase.txt");
+        let phase_function =
+            PhaseFunction::parse(&fs::read_to_string(&phase_path)?).ok_or_else(|| {
+                spectral_data::invalid_data(&phase_path, "unknown phase function".to_owned())
+            })?;
+
+        let cross_section_table = Table::load(&directory.join("cross_section.csv"), 2)?;
+
+        let cross_sections = (0..SPECTRAL_ATLAS_WIDTH)
+            .map(|index| {
+                let wavelength = SPECTRAL_ATLAS_START + index as f32;
+
+                [0, 1].map(|column| cross_section_table.sample(wavelength, column))
+            })
+            .collect();
+
+        let density_path = directory.join("density.csv");
+        let density_table = Table::load(&density_path, 1)?;
+
+        let max_altitude = density_table.last_key();
+
+        if max_altitude <= 0.0 {
+            return Err(spectral_data::invalid_data(
+                &density_path,
+                "needs altitudes above the ground".to_owned(),
+            ));
+        }
+
+        let number_densities = (0..DENSITY_PROFILE_RESOLUTION)
+            .map(|index| {
+                let altitude =
+                    max_altitude * index as f32 / (DENSITY_PROFILE_RESOLUTION - 1) as f32;
+
+                density_table.sample(altitude, 0)
+            })
+            .collect();
+
+        let name = directory
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(Self {
+            name,
+            phase_function,
+            cross_sections,
+            number_densities,
+            max_altitude,
+        })
+    }
+
+    // Used for the majorant of delta tracking
+    pub fn max_number_density(&self) -> f32 {
+        self.number_densities.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+// A set of constituents making up an atmosphere, loaded from a directory in assets/atmospheres with a subdirectory
+// per constituent
+pub struct Composition {
+    // Directory name
+    pub name: String,
+    pub constituents: Vec<Constituent>,
+}
+
+impl Composition {
+    fn load(directory: &Path) -> io::Result<Self> {
+        let mut paths: Vec<_> = fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+
+        // keep indices stable between runs
+        paths.sort();
+
+        let constituents = paths
+            .iter()
+            .map(|path| Constituent::load(path))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if constituents.is_empty() {
+            return Err(spectral_data::invalid_data(
+                directory,
+                "has no constituents".to_owned(),
+            ));
+        }
+
+        let name = directory
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(Self { name, constituents })
+    }
+}
+
+/// Atmosphere compositions loaded from assets/atmospheres. The constituents of all compositions are packed into the
+/// rows of a cross section and a number density texture, one after the other. The first row is left empty, so
+/// constituent index 0 stands for no constituent, like the null elements of the object buffers.
+pub struct Compositions {
+    pub compositions: Vec<Composition>,
+}
+
+impl Compositions {
+    pub fn load() -> Self {
+        let mut compositions = Vec::new();
+
+        // having no compositions is fine, the built-in model is always available
+        let Ok(entries) = fs::read_dir(util::asset_path("atmospheres")) else {
+            return Self { compositions };
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+
+        paths.sort();
+
+        for path in paths {
+            match Composition::load(&path) {
+                Ok(composition) => compositions.push(composition),
+                Err(err) => log::error!("Failed to load atmosphere composition: {}", err),
+            }
+        }
+
+        Self { compositions }
+    }
+
+    pub fn name(&self, composition: usize) -> Option<&str> {
+        self.compositions
+            .get(composition)
+            .map(|composition| composition.name.as_str())
+    }
+
+    // Index of the first constituent of a composition, and its number of constituents
+    pub fn constituent_range(&self, composition: usize) -> (u32, u32) {
+        let start = 1 + self
+            .compositions
+            .iter()
+            .take(composition)
+            .map(|composition| composition.constituents.len() as u32)
+            .sum::<u32>();
+
+        let count = self
+            .compositions
+            .get(composition)
+            .map_or(0, |composition| composition.constituents.len() as u32);
+
+        (start, count)
+    }
+
+    // Including the empty first row
+    pub fn constituents(&self) -> impl Iterator<Item = Option<&Constituent>> {
+        std::iter::once(None).chain(
+            self.compositions
+                .iter()
+                .flat_map(|composition| composition.constituents.iter().map(Some)),
+        )
+    }
+
+    // Rg32Float texels of the cross section texture, with a row per constituent
+    pub fn cross_section_texels(&self) -> Vec<f32> {
+        let empty_row = vec![[0.0; 2]; SPECTRAL_ATLAS_WIDTH as usize];
+
+        self.constituents()
+            .flat_map(|constituent| {
+                constituent.map_or(&empty_row, |constituent| &constituent.cross_sections)
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    // R32Float texels of the number density texture, with a row per constituent
+    pub fn number_density_texels(&self) -> Vec<f32> {
+        let empty_row = vec![0.0; DENSITY_PROFILE_RESOLUTION as usize];
+
+        self.constituents()
+            .flat_map(|constituent| {
+                constituent.map_or(&empty_row, |constituent| &constituent.number_densities)
+            })
+            .copied()
+            .collect()
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    f32::consts::PI,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use glam::UVec2;
+use wgpu::util::DeviceExt;
+
+use crate::{app::spectral_data, util};
+
+// Equirectangular radiance texture, along with the distributions its texels are importance sampled by
+struct EnvironmentMap {
+    view: wgpu::TextureView,
+
+    // Cumulative distributions normalized to end at 1. The marginal one picks a row and has height + 1 entries, the
+    // conditional ones pick a texel within each row and have width + 1 entries per row.
+    marginal_cdf_buffer: wgpu::Buffer,
+    conditional_cdf_buffer: wgpu::Buffer,
+}
+
+impl EnvironmentMap {
+    fn create(device: &wgpu::Device, queue: &wgpu::Queue, size: UVec2, texels: &[f32]) -> Self {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("environment_map_texture"),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(texels),
+        );
+
+        let (marginal_cdf, conditional_cdf) = build_distributions(size, texels);
+
+        let create_buffer = |label: &str, cdf: &[f32]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(cdf),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        };
+
+        Self {
+            view: texture.create_view(&Default::default()),
+            marginal_cdf_buffer: create_buffer("environment_marginal_cdf_buffer", &marginal_cdf),
+            conditional_cdf_buffer: create_buffer(
+                "environment_conditional_cdf_buffer",
+                &conditional_cdf,
+            ),
+        }
+    }
+
+    // Loads a Radiance HDR or OpenEXR image
+    fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> io::Result<Self> {
+        let image =
+            image::open(path).map_err(|err| spectral_data::invalid_data(path, err.to_string()))?;
+
+        let size = UVec2::new(image.width(), image.height());
+        let max_size = device.limits().max_texture_dimension_2d;
+
+        if size.max_element() > max_size {
+            return Err(spectral_data::invalid_data(
+                path,
+                format!("is larger than the maximum texture size of {}", max_size),
+            ));
+        }
+
+        // the conditional distributions are the largest buffer, a float for every texel and one more per row
+        let conditional_size = (size.x as u64 + 1) * size.y as u64 * 4;
+        let max_buffer_size = device.limits().max_storage_buffer_binding_size as u64;
+
+        if conditional_size > max_buffer_size {
+            return Err(spectral_data::invalid_data(
+                path,
+                format!(
+                    "needs a {} byte sampling distribution, but storage buffers are limited to {} bytes",
+                    conditional_size, max_buffer_size
+                ),
+            ));
+        }
+
+        let pixels = image.to_rgba32f();
+
+        Ok(Self::create(device, queue, size, pixels.as_raw()))
+    }
+
+    // Black, for when no map is selected or it failed to load
+    fn placeholder(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::create(device, queue, UVec2::ONE, &[0.0, 0.0, 0.0, 1.0])
+    }
+
+    fn resources<'a>(&'a self, sampler: &'a wgpu::Sampler) -> [wgpu::BindingResource<'a>; 4] {
+        [
+            wgpu::BindingResource::TextureView(&self.view),
+            wgpu::BindingResource::Sampler(sampler),
+            self.marginal_cdf_buffer.as_entire_binding(),
+            self.conditional_cdf_buffer.as_entire_binding(),
+        ]
+    }
+}
+
+// Texels are sampled proportionally to their luminance times the solid angle they cover, which shrinks towards the
+// poles of the equirectangular projection
+fn build_distributions(size: UVec2, texels: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    let (width, height) = (size.x as usize, size.y as usize);
+
+    let mut marginal_cdf = Vec::with_capacity(height + 1);
+    let mut conditional_cdf = Vec::with_capacity((width + 1) * height);
+
+    marginal_cdf.push(0.0);
+
+    for (y, row) in texels.chunks_exact(4 * width).enumerate() {
+        let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+
+        let start = conditional_cdf.len();
+        let mut sum = 0.0;
+
+        conditional_cdf.push(sum);
+
+        for texel in row.chunks_exact(4) {
+            // NaN and infinite texels would make the whole distribution NaN, they're never sampled instead
+            let luminance = 0.2126 * texel[0] + 0.7152 * texel[1] + 0.0722 * texel[2];
+            let luminance = if luminance.is_finite() {
+                luminance.max(0.0)
+            } else {
+                0.0
+            };
+
+            sum += luminance * sin_theta;
+
+            conditional_cdf.push(sum);
+        }
+
+        normalize_cdf(&mut conditional_cdf[start..]);
+
+        let row_start = marginal_cdf[y];
+        marginal_cdf.push(row_start + sum);
+    }
+
+    normalize_cdf(&mut marginal_cdf);
+
+    (marginal_cdf, conditional_cdf)
+}
+
+// Scales a running sum to end at 1, or makes it uniform if there's nothing to sample
+fn normalize_cdf(cdf: &mut [f32]) {
+    let total = cdf[cdf.len() - 1];
+    let steps = (cdf.len() - 1) as f32;
+
+    for (index, value) in cdf.iter_mut().enumerate() {
+        *value = if total > 0.0 {
+            *value / total
+        } else {
+            index as f32 / steps
+        };
+    }
+}
+
+/// Equirectangular HDR and EXR images in assets/environments, which light the scene instead of the atmosphere with
+/// `SkyMode::Environment`. Only the selected map is kept on the GPU, since they're large.
+pub struct EnvironmentMaps {
+    paths: Vec<PathBuf>,
+    // File names without the extension
+    pub names: Vec<String>,
+
+    selected: Option<usize>,
+
+    sampler: wgpu::Sampler,
+
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl EnvironmentMaps {
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let mut paths: Vec<PathBuf> = fs::read_dir(util::asset_path("environments"))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| {
+                        path.extension()
+                            .and_then(|extension| extension.to_str())
+                            .is_some_and(|extension| {
+                                matches!(extension.to_ascii_lowercase().as_str(), "hdr" | "exr")
+                            })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // keep indices stable between runs
+        paths.sort();
+
+        let names = paths
+            .iter()
+            .map(|path| {
+                path.file_stem()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        // wraps around horizontally, but not over the poles
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("environment_map_sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let placeholder = EnvironmentMap::placeholder(device, queue);
+
+        let (bind_group_layout, bind_group) = wgputil::binding::create_sequential_linked(
+            device,
+            "environment_binding",
+            &[
+                wgputil::binding::bind_texture(
+                    &placeholder.view,
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::TextureViewDimension::D2,
+                ),
+                wgputil::binding::bind_sampler(&sampler, wgpu::SamplerBindingType::Filtering),
+                wgputil::binding::bind_buffer_storage(&placeholder.marginal_cdf_buffer, true),
+                wgputil::binding::bind_buffer_storage(&placeholder.conditional_cdf_buffer, true),
+            ],
+        );
+
+        Self {
+            paths,
+            names,
+            selected: None,
+            sampler,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    // Uploads the selected map if it changed, falling back to black if there's none or it can't be loaded
+    pub fn select(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, selected: Option<usize>) {
+        if selected == self.selected {
+            return;
+        }
+
+        self.selected = selected;
+
+        let map = match selected.and_then(|index| self.paths.get(index)) {
+            Some(path) => EnvironmentMap::load(device, queue, path).unwrap_or_else(|err| {
+                log::error!("Failed to load environment map: {}", err);
+                EnvironmentMap::placeholder(device, queue)
+            }),
+            None => EnvironmentMap::placeholder(device, queue),
+        };
+
+        self.bind_group = wgputil::binding::create_sequential_with_layout(
+            device,
+            "environment_binding",
+            &self.bind_group_layout,
+            &map.resources(&self.sampler),
+        );
+    }
+
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(index).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_valid_cdf(cdf: &[f32]) {
+        assert_eq!(cdf[0], 0.0);
+        assert!((cdf[cdf.len() - 1] - 1.0).abs() < 1e-6, "{:?}", cdf);
+        assert!(cdf.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", cdf);
+    }
+
+    // Rows of the conditional distributions of a map
+    fn rows(size: UVec2, conditional_cdf: &[f32]) -> impl Iterator<Item = &[f32]> {
+        conditional_cdf.chunks_exact(size.x as usize + 1)
+    }
+
+    #[test]
+    fn distributions_are_valid_cdfs() {
+        let size = UVec2::new(4, 3);
+        let mut texels: Vec<f32> = (0..size.x * size.y)
+            .flat_map(|index| [index as f32, 0.5, 0.25, 1.0])
+            .collect();
+
+        // negative, NaN and infinite texels aren't sampled
+        texels[4..8].copy_from_slice(&[-1.0, -1.0, -1.0, 1.0]);
+        texels[8..12].copy_from_slice(&[f32::NAN, 0.0, 0.0, 1.0]);
+        texels[12..16].copy_from_slice(&[f32::INFINITY, 0.0, 0.0, 1.0]);
+
+        let (marginal_cdf, conditional_cdf) = build_distributions(size, &texels);
+
+        assert_eq!(marginal_cdf.len(), size.y as usize + 1);
+        assert_eq!(
+            conditional_cdf.len(),
+            (size.x as usize + 1) * size.y as usize
+        );
+
+        assert_valid_cdf(&marginal_cdf);
+        rows(size, &conditional_cdf).for_each(assert_valid_cdf);
+
+        // the texels that aren't sampled have no probability
+        let first_row = &conditional_cdf[..size.x as usize + 1];
+        assert_eq!(first_row[1], first_row[2]);
+        assert_eq!(first_row[2], first_row[3]);
+        assert_eq!(first_row[3], first_row[4]);
+    }
+
+    #[test]
+    fn black_map_is_sampled_uniformly() {
+        let size = UVec2::new(4, 2);
+        let texels = [0.0, 0.0, 0.0, 1.0].repeat((size.x * size.y) as usize);
+
+        let (marginal_cdf, conditional_cdf) = build_distributions(size, &texels);
+
+        assert_eq!(marginal_cdf, [0.0, 0.5, 1.0]);
+
+        for row in rows(size, &conditional_cdf) {
+            assert_eq!(row, [0.0, 0.25, 0.5, 0.75, 1.0]);
+        }
+    }
+}
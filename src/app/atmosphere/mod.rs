@@ -0,0 +1,66 @@
+use bevy_ecs::{resource::Resource, system::Commands};
+use glam::Vec3;
+
+pub mod binding;
+pub mod composition;
+pub mod environment;
+
+/// Parameters of the planet and its atmosphere, in meters. Editable in the menu, and uploaded to the GPU by
+/// `AtmosphereBinding` whenever they change.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct Atmosphere {
+    pub planet_radius: f32,
+    // Thickness of the atmosphere shell above the ground
+    pub atmosphere_height: f32,
+    // Altitude of the world origin above the ground
+    pub origin_altitude: f32,
+
+    pub ground_albedo: f32,
+
+    pub rayleigh_scale_height: f32,
+    pub mie_scale_height: f32,
+
+    // Henyey-Greenstein asymmetry parameter of Mie scattering, positive values scatter forward
+    pub mie_anisotropy: f32,
+    // Scales the amount of aerosols causing Mie scattering, 1 is a very clear sky
+    pub turbidity: f32,
+
+    pub ozone_peak_altitude: f32,
+    pub ozone_width: f32,
+    // Multiplier of the ozone absorption, 1 is Earth
+    pub ozone_amount: f32,
+
+    // Index of a loaded composition that replaces the built-in Rayleigh, Mie and ozone model, along with the
+    // parameters above that are specific to it
+    pub composition: Option<usize>,
+}
+
+impl Atmosphere {
+    pub fn init(mut commands: Commands) {
+        commands.insert_resource(Self::default());
+    }
+
+    pub fn planet_position(&self) -> Vec3 {
+        Vec3::new(0.0, -self.planet_radius - self.origin_altitude, 0.0)
+    }
+}
+
+impl Default for Atmosphere {
+    // Earth
+    fn default() -> Self {
+        Self {
+            planet_radius: 6.360e6,
+            atmosphere_height: 100.0e3,
+            origin_altitude: 500.0,
+            ground_albedo: 0.15,
+            rayleigh_scale_height: 8652.2,
+            mie_scale_height: 1400.0,
+            mie_anisotropy: 0.75,
+            turbidity: 1.0,
+            ozone_peak_altitude: 22.0e3,
+            ozone_width: 6092.04,
+            ozone_amount: 1.0,
+            composition: None,
+        }
+    }
+}
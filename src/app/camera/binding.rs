@@ -7,7 +7,7 @@ use gpu_bytes::AsStd140;
 use gpu_bytes_derive::{AsStd140, AsStd430};
 use wgpu::util::DeviceExt;
 
-use crate::app::renderer::{RendererViewport, SurfaceState};
+use crate::app::renderer::{RenderResolution, RendererViewport, SurfaceState};
 
 use super::Camera;
 
@@ -73,6 +73,7 @@ impl ScreenBinding {
     pub fn update(
         surface_state: Res<SurfaceState>,
         renderer_viewport: Res<RendererViewport>,
+        render_resolution: Res<RenderResolution>,
         mut screen_binding: ResMut<ScreenBinding>,
         camera: Res<Camera>,
     ) {
@@ -84,9 +85,11 @@ impl ScreenBinding {
             0,
         );
 
-        screen_binding
-            .view_uniform
-            .update_from(&renderer_viewport, &surface_state);
+        screen_binding.view_uniform.update_from(
+            &render_resolution,
+            &renderer_viewport,
+            &surface_state,
+        );
         wgputil::buffer::write_slice(
             &surface_state.gpu.queue,
             &screen_binding.view_buffer,
@@ -121,8 +124,15 @@ pub struct CameraUniform {
 }
 
 impl CameraUniform {
+    /// Whether the camera hasn't changed since last frame.
+    pub fn is_still(&self) -> bool {
+        self.position == self.previous_position
+            && self.view == self.previous_view
+            && self.projection_matrix.x_axis == self.previous_projection_matrix.x_axis
+    }
+
     fn update_from(&mut self, camera: &Camera) {
-        self.previous_projection_matrix = self.view_projection_matrix;
+        self.previous_view_projection_matrix = self.view_projection_matrix;
         self.previous_view_matrix = self.view_matrix;
         self.previous_projection_matrix = self.projection_matrix;
 
@@ -147,6 +157,8 @@ impl CameraUniform {
 
 #[derive(AsStd140, AsStd430, Default)]
 pub struct ViewUniform {
+    render_width: u32,
+    render_height: u32,
     renderer_viewport_width: u32,
     renderer_viewport_height: u32,
     window_width: u32,
@@ -156,13 +168,19 @@ pub struct ViewUniform {
 }
 
 impl ViewUniform {
-    fn update_from(&mut self, renderer_viewport: &RendererViewport, surface_state: &SurfaceState) {
+    fn update_from(
+        &mut self,
+        render_resolution: &RenderResolution,
+        renderer_viewport: &RendererViewport,
+        surface_state: &SurfaceState,
+    ) {
+        self.render_width = render_resolution.width;
+        self.render_height = render_resolution.height;
         self.renderer_viewport_width = renderer_viewport.get_width();
         self.renderer_viewport_height = renderer_viewport.get_height();
         self.window_width = surface_state.viewport_size.width;
         self.window_height = surface_state.viewport_size.height;
-        self.aspect_ratio =
-            self.renderer_viewport_width as f32 / self.renderer_viewport_height as f32;
+        self.aspect_ratio = self.render_width as f32 / self.render_height as f32;
         self.frame_count = self.frame_count.wrapping_add(1);
     }
 }
@@ -7,7 +7,7 @@ use glam::{DVec2, Mat3, Mat4, Quat, Vec3};
 use winit::{dpi::PhysicalSize, keyboard::KeyCode};
 
 use crate::app::events::MouseMotion;
-use crate::app::renderer::RendererViewport;
+use crate::app::renderer::RenderResolution;
 
 use super::{input::Input, time::Time};
 
@@ -162,12 +162,12 @@ impl Camera {
         (rotation, yaw, pitch)
     }
 
-    pub fn init(mut commands: Commands, renderer_viewport: Res<RendererViewport>) {
+    pub fn init(mut commands: Commands, render_resolution: Res<RenderResolution>) {
         commands.insert_resource(Camera::new(
             Vec3::ZERO,
             Vec3::Z,
             45.0,
-            renderer_viewport.get_size(),
+            render_resolution.get_size(),
             1.0,
             100.0,
             10.0,
@@ -186,7 +186,7 @@ impl Camera {
         camera.update_rotation(mouse_delta, 0.1);
     }
 
-    pub fn on_resize(mut camera: ResMut<Camera>, renderer_viewport: Res<RendererViewport>) {
-        camera.reconfigure_aspect(renderer_viewport.get_size());
+    pub fn on_resize(mut camera: ResMut<Camera>, render_resolution: Res<RenderResolution>) {
+        camera.reconfigure_aspect(render_resolution.get_size());
     }
 }
@@ -4,6 +4,9 @@ use derive_deref::{Deref, DerefMut};
 #[derive(Event)]
 pub struct MenuResizeEvent;
 
+#[derive(Event)]
+pub struct ExportEvent;
+
 #[derive(Event, Deref, DerefMut)]
 pub struct MouseMotion(pub glam::DVec2);
 
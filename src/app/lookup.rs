@@ -2,12 +2,76 @@ use bevy_ecs::{
     resource::Resource,
     system::{Commands, Res},
 };
+use wgpu::util::DeviceExt;
 use wgputil::GpuHandle;
 
-use crate::{app::renderer::SurfaceState, util};
+use crate::{
+    app::{
+        renderer::SurfaceState,
+        spectral_data::{SpectralAtlas, SPECTRAL_ATLAS_WIDTH},
+    },
+    util,
+};
+
+// Matches the constants in `Spectrum` in spectrum.slang
+const WAVELENGTH_TO_XYZ_LUT_START: f32 = 360.0;
+const WAVELENGTH_RANGE_START: f32 = 380.0;
+const WAVELENGTH_RANGE_END: f32 = 780.0;
+const VISIBLE_WAVELENGTH_INVERSE_CDF_SIZE: usize = 256;
+const RGB_TO_SPECTRUM_RESOLUTION: u32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavelengthSampling {
+    Uniform,
+    // Proportional to the sum of the CIE color matching functions
+    Visible,
+}
+
+// Builds a table of the wavelengths at evenly spaced values of the CDF of x bar + y bar + z bar, from the Rgba32Float
+// texels of wavelength_to_xyz.bin, which start at 360nm and are 1nm apart
+fn visible_wavelength_inverse_cdf(wavelength_to_xyz: &[u8]) -> Vec<f32> {
+    let xyz_sum = |wavelength: f32| {
+        let index = (wavelength - WAVELENGTH_TO_XYZ_LUT_START) as usize;
+        let texel = &wavelength_to_xyz[index * 16..index * 16 + 12];
+
+        texel
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .sum::<f32>()
+    };
+
+    // trapezoidal integration over 1nm steps
+    let steps = (WAVELENGTH_RANGE_END - WAVELENGTH_RANGE_START) as usize;
+    let mut cdf = vec![0.0; steps + 1];
+
+    for step in 0..steps {
+        let wavelength = WAVELENGTH_RANGE_START + step as f32;
+        cdf[step + 1] = cdf[step] + 0.5 * (xyz_sum(wavelength) + xyz_sum(wavelength + 1.0));
+    }
+
+    let total = cdf[steps];
+
+    (0..VISIBLE_WAVELENGTH_INVERSE_CDF_SIZE)
+        .map(|index| {
+            let u = index as f32 / (VISIBLE_WAVELENGTH_INVERSE_CDF_SIZE - 1) as f32 * total;
+
+            // first step whose end reaches u, then invert linearly within it
+            let step = cdf[1..]
+                .iter()
+                .position(|&value| value >= u)
+                .unwrap_or(steps - 1);
+
+            let fraction = (u - cdf[step]) / (cdf[step + 1] - cdf[step]).max(f32::EPSILON);
+
+            WAVELENGTH_RANGE_START + step as f32 + fraction.clamp(0.0, 1.0)
+        })
+        .collect()
+}
 
 #[derive(Resource)]
 pub struct SpectrumBinding {
+    pub atlas: SpectralAtlas,
+
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
@@ -56,6 +120,102 @@ impl SpectrumBinding {
         )
         .unwrap();
 
+        let wavelength_to_xyz =
+            std::fs::read(util::asset_path("textures/spectrum/wavelength_to_xyz.bin"))
+                .expect("Failed to read the wavelength to XYZ LUT");
+
+        let inverse_cdf = visible_wavelength_inverse_cdf(&wavelength_to_xyz);
+
+        let visible_wavelength_inverse_cdf_texture = gpu.device.create_texture_with_data(
+            &gpu.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("visible_wavelength_inverse_cdf_texture"),
+                size: wgpu::Extent3d {
+                    width: inverse_cdf.len() as u32,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D1,
+                format: wgpu::TextureFormat::R32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&inverse_cdf),
+        );
+
+        // Generated by the rgb_to_spectrum tool, see src/bin/rgb_to_spectrum.rs
+        let rgb_to_spectrum_scale_texture = wgputil::texture::load_raw(
+            &gpu.device,
+            &gpu.queue,
+            util::asset_path("textures/spectrum/rgb_to_spectrum_scale.bin"),
+            &wgpu::TextureDescriptor {
+                label: Some("rgb_to_spectrum_scale_texture"),
+                size: wgpu::Extent3d {
+                    width: RGB_TO_SPECTRUM_RESOLUTION,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D1,
+                format: wgpu::TextureFormat::R32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+        )
+        .unwrap();
+
+        let rgb_to_spectrum_coefficients = std::fs::read(util::asset_path(
+            "textures/spectrum/rgb_to_spectrum_coefficients.bin",
+        ))
+        .expect("Failed to read the RGB to spectrum coefficients");
+
+        // The three tables, one per largest color component, are stacked along the depth
+        let rgb_to_spectrum_coefficients_texture = gpu.device.create_texture_with_data(
+            &gpu.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("rgb_to_spectrum_coefficients_texture"),
+                size: wgpu::Extent3d {
+                    width: RGB_TO_SPECTRUM_RESOLUTION,
+                    height: RGB_TO_SPECTRUM_RESOLUTION,
+                    depth_or_array_layers: 3 * RGB_TO_SPECTRUM_RESOLUTION,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &rgb_to_spectrum_coefficients,
+        );
+
+        let atlas = SpectralAtlas::load();
+
+        let spectral_atlas_texture = gpu.device.create_texture_with_data(
+            &gpu.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("spectral_atlas_texture"),
+                size: wgpu::Extent3d {
+                    width: SPECTRAL_ATLAS_WIDTH,
+                    height: atlas.height(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rg32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&atlas.texels()),
+        );
+
         let (bind_group_layout, bind_group) = wgputil::binding::create_sequential_linked(
             &gpu.device,
             "spectrum_binding",
@@ -72,10 +232,40 @@ impl SpectrumBinding {
                     wgpu::TextureViewDimension::D1,
                     wgpu::StorageTextureAccess::ReadWrite,
                 ),
+                wgputil::binding::bind_texture(
+                    &visible_wavelength_inverse_cdf_texture.create_view(&Default::default()),
+                    wgputil::texture::sample_type(
+                        &gpu.device,
+                        &visible_wavelength_inverse_cdf_texture,
+                    )
+                    .unwrap(),
+                    wgpu::TextureViewDimension::D1,
+                ),
+                wgputil::binding::bind_texture(
+                    &rgb_to_spectrum_scale_texture.create_view(&Default::default()),
+                    wgputil::texture::sample_type(&gpu.device, &rgb_to_spectrum_scale_texture)
+                        .unwrap(),
+                    wgpu::TextureViewDimension::D1,
+                ),
+                wgputil::binding::bind_texture(
+                    &rgb_to_spectrum_coefficients_texture.create_view(&Default::default()),
+                    wgputil::texture::sample_type(
+                        &gpu.device,
+                        &rgb_to_spectrum_coefficients_texture,
+                    )
+                    .unwrap(),
+                    wgpu::TextureViewDimension::D3,
+                ),
+                wgputil::binding::bind_texture(
+                    &spectral_atlas_texture.create_view(&Default::default()),
+                    wgputil::texture::sample_type(&gpu.device, &spectral_atlas_texture).unwrap(),
+                    wgpu::TextureViewDimension::D2,
+                ),
             ],
         );
 
         Self {
+            atlas,
             bind_group_layout,
             bind_group,
         }
@@ -1,17 +1,32 @@
 use std::sync::Arc;
 
 use bevy_ecs::{
+    change_detection::DetectChangesMut,
     event::EventWriter,
     resource::Resource,
     system::{Res, ResMut},
     world::World,
 };
 use egui::{DragValue, Ui};
-use glam::{UVec2, Vec3};
+use glam::{UVec2, Vec2, Vec3};
 use winit::window::Window;
 
 use crate::{
-    app::{events::MenuResizeEvent, renderer::RendererViewport},
+    app::{
+        atmosphere::{
+            binding::AtmosphereBinding, composition::Compositions, environment::EnvironmentMaps,
+            Atmosphere,
+        },
+        events::{ExportEvent, MenuResizeEvent},
+        lookup::{SpectrumBinding, WavelengthSampling},
+        renderer::{
+            denoise::{DenoiserSettings, MAX_ATROUS_ITERATIONS},
+            display::{DebugView, DisplayFilter, DisplayView},
+            material::{PathtraceDebugMode, PathtraceSettings, RouletteStrategy, SkyMode},
+            RenderResolution, RendererViewport, ResolutionMode,
+        },
+        spectral_data::{SpectralAtlas, SpectrumKind},
+    },
     ecs::ResourceWrapper,
     egui::EguiRenderState,
 };
@@ -19,10 +34,235 @@ use crate::{
 use super::{
     camera::Camera,
     fps::FpsCounter,
-    object::{Aabb, Material, MaterialType, Objects, Sphere, Triangle},
-    renderer::profiler::RenderProfiler,
+    object::{
+        binding::ObjectBinding, density_grid::DensityGrids, texture::ImageTextures, Aabb,
+        DensityType, Material, MaterialType, Medium, Objects, Sphere, Triangle,
+    },
+    renderer::{accumulation::Accumulation, profiler::RenderProfiler},
 };
 
+// Temperature a material starts at when switching it to blackbody emission, roughly daylight
+const DEFAULT_BLACKBODY_TEMPERATURE: f32 = 6500.0;
+
+// Mean free path a material starts at when switching it to subsurface scattering, red light goes the furthest like
+// in skin
+const DEFAULT_MEAN_FREE_PATH: Vec3 = Vec3::new(0.1, 0.04, 0.02);
+
+// Refractive index of the thin film of principled materials when it's first turned on, like a soap bubble
+const DEFAULT_FILM_IOR: f32 = 1.33;
+
+// Smallest and largest size in pixels of each side in the fixed resolution mode, where the smallest is still a few
+// workgroups
+const FIXED_RESOLUTION_RANGE: std::ops::RangeInclusive<u32> = 64..=8192;
+
+// Noise frequency of the film thickness when it's first made to vary, in features per unit
+const DEFAULT_FILM_NOISE_FREQUENCY: f32 = 4.0;
+
+// Picks a spectrum of the given kind from the atlas, or none to use the material's RGB values
+fn spectrum_selector(
+    ui: &mut Ui,
+    id: &str,
+    index: &mut u32,
+    atlas: &SpectralAtlas,
+    kind: SpectrumKind,
+) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(atlas.name(*index).unwrap_or("RGB"))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(index, 0, "RGB");
+
+            for (spectrum_index, spectrum) in atlas.of_kind(kind) {
+                ui.selectable_value(index, spectrum_index, &spectrum.name);
+            }
+        });
+}
+
+// Picks a material texture, or none
+fn texture_selector(ui: &mut Ui, label: &str, index: &mut u32, image_textures: &ImageTextures) {
+    egui::ComboBox::from_label(label)
+        .selected_text(image_textures.name(*index).unwrap_or("None"))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(index, 0, "None");
+
+            // the first texture is the placeholder
+            for (texture_index, texture) in image_textures.textures.iter().enumerate().skip(1) {
+                ui.selectable_value(index, texture_index as u32, &texture.name);
+            }
+        });
+}
+
+// Texture slots of a material, from the images in assets/textures/materials
+fn texture_slots_editor(ui: &mut Ui, material: &mut Material, image_textures: &ImageTextures) {
+    ui.collapsing("Textures", |ui| {
+        if image_textures.textures.len() <= 1 {
+            ui.label("No images in assets/textures/materials");
+            return;
+        }
+
+        texture_selector(ui, "Albedo", &mut material.albedo_texture, image_textures);
+        texture_selector(
+            ui,
+            "Roughness",
+            &mut material.roughness_texture,
+            image_textures,
+        );
+        texture_selector(ui, "Normal", &mut material.normal_texture, image_textures);
+        texture_selector(
+            ui,
+            "Emission",
+            &mut material.emission_texture,
+            image_textures,
+        );
+    });
+}
+
+fn atmosphere_editor(ui: &mut Ui, atmosphere: &mut Atmosphere, compositions: &Compositions) {
+    let length = |ui: &mut Ui, label: &str, value: &mut f32, speed: f32| {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            ui.add(
+                DragValue::new(value)
+                    .speed(speed)
+                    .range(1.0..=f32::INFINITY)
+                    .suffix(" m"),
+            );
+        });
+    };
+
+    ui.collapsing("Planet", |ui| {
+        length(ui, "Planet radius", &mut atmosphere.planet_radius, 1000.0);
+        length(
+            ui,
+            "Atmosphere height",
+            &mut atmosphere.atmosphere_height,
+            100.0,
+        );
+        length(ui, "Origin altitude", &mut atmosphere.origin_altitude, 10.0);
+
+        ui.add(egui::Slider::new(&mut atmosphere.ground_albedo, 0.0..=1.0).text("Ground albedo"));
+    });
+
+    egui::ComboBox::from_label("Composition")
+        .selected_text(
+            atmosphere
+                .composition
+                .and_then(|composition| compositions.name(composition))
+                .unwrap_or("Built-in"),
+        )
+        .show_ui(ui, |ui| {
+            let selected = &mut atmosphere.composition;
+
+            ui.selectable_value(selected, None, "Built-in");
+
+            for (index, composition) in compositions.compositions.iter().enumerate() {
+                ui.selectable_value(selected, Some(index), &composition.name);
+            }
+        });
+
+    if let Some(composition) = atmosphere
+        .composition
+        .and_then(|composition| compositions.compositions.get(composition))
+    {
+        let names: Vec<_> = composition
+            .constituents
+            .iter()
+            .map(|constituent| constituent.name.as_str())
+            .collect();
+
+        ui.label(format!("Constituents: {}", names.join(", ")));
+    }
+
+    // these only apply to the built-in model
+    ui.add_enabled_ui(atmosphere.composition.is_none(), |ui| {
+        ui.collapsing("Scattering", |ui| {
+            length(
+                ui,
+                "Rayleigh scale height",
+                &mut atmosphere.rayleigh_scale_height,
+                10.0,
+            );
+            length(
+                ui,
+                "Mie scale height",
+                &mut atmosphere.mie_scale_height,
+                10.0,
+            );
+
+            ui.add(
+                egui::Slider::new(&mut atmosphere.mie_anisotropy, -0.99..=0.99)
+                    .text("Mie anisotropy"),
+            );
+            ui.add(egui::Slider::new(&mut atmosphere.turbidity, 1.0..=10.0).text("Turbidity"));
+        });
+
+        ui.collapsing("Ozone", |ui| {
+            length(
+                ui,
+                "Peak altitude",
+                &mut atmosphere.ozone_peak_altitude,
+                100.0,
+            );
+            length(ui, "Layer width", &mut atmosphere.ozone_width, 10.0);
+
+            ui.add(egui::Slider::new(&mut atmosphere.ozone_amount, 0.0..=10.0).text("Amount"));
+        });
+    });
+
+    if ui.button("Reset atmosphere").clicked() {
+        *atmosphere = Atmosphere::default();
+    }
+}
+
+fn environment_editor(
+    ui: &mut Ui,
+    settings: &mut PathtraceSettings,
+    environment_maps: &EnvironmentMaps,
+) {
+    egui::ComboBox::from_label("Environment map")
+        .selected_text(
+            settings
+                .environment_map
+                .and_then(|index| environment_maps.name(index))
+                .unwrap_or("None"),
+        )
+        .show_ui(ui, |ui| {
+            let selected = &mut settings.environment_map;
+
+            ui.selectable_value(selected, None, "None");
+
+            for (index, name) in environment_maps.names.iter().enumerate() {
+                ui.selectable_value(selected, Some(index), name);
+            }
+        })
+        .response
+        .on_hover_text("Equirectangular HDR or EXR images in assets/environments");
+
+    ui.add(
+        egui::Slider::new(&mut settings.environment_rotation, -180.0..=180.0)
+            .text("Rotation")
+            .suffix("°"),
+    );
+
+    ui.horizontal(|ui| {
+        ui.label("Intensity");
+        ui.add(
+            DragValue::new(&mut settings.environment_intensity)
+                .speed(0.01)
+                .range(0.0..=f32::INFINITY),
+        );
+    });
+}
+
+fn uv_editor(value: &mut Vec2, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.label("U");
+        ui.add(DragValue::new(&mut value.x).fixed_decimals(2).speed(0.01));
+
+        ui.label("V");
+        ui.add(DragValue::new(&mut value.y).fixed_decimals(2).speed(0.01));
+    });
+}
+
 fn vec3_editor(value: &mut Vec3, ui: &mut Ui) {
     ui.horizontal(|ui| {
         ui.label("X");
@@ -36,6 +276,140 @@ fn vec3_editor(value: &mut Vec3, ui: &mut Ui) {
     });
 }
 
+// Edits the channels of an RGB value that unlike a color isn't limited to 1, like the coefficients of a medium
+fn rgb_editor(ui: &mut Ui, label: &str, value: &mut Vec3) {
+    ui.label(label);
+    ui.horizontal(|ui| {
+        for (channel, component) in ["R", "G", "B"].into_iter().zip(value.as_mut()) {
+            ui.label(channel);
+            ui.add(
+                DragValue::new(component)
+                    .fixed_decimals(2)
+                    .speed(0.01)
+                    .range(0.0..=f32::INFINITY),
+            );
+        }
+    });
+}
+
+// Layers of principled materials, in the order light goes through them. The thin film has its own editor.
+fn principled_editor(ui: &mut Ui, material: &mut Material) {
+    ui.collapsing("Coat", |ui| {
+        ui.add(egui::Slider::new(&mut material.coat, 0.0..=1.0).text("Weight"));
+        ui.add(egui::Slider::new(&mut material.coat_roughness, 0.0..=1.0).text("Roughness"));
+    });
+
+    ui.collapsing("Base", |ui| {
+        ui.add(egui::Slider::new(&mut material.metalness, 0.0..=1.0).text("Metalness"));
+        ui.add(egui::Slider::new(&mut material.roughness, 0.0..=1.0).text("Roughness"));
+    });
+
+    ui.collapsing("Specular", |ui| {
+        ui.add(egui::Slider::new(&mut material.specular, 0.0..=1.0).text("Weight"));
+        ui.add(egui::Slider::new(&mut material.ior, 1.0..=4.0).text("Refractive Index"));
+    });
+
+    ui.collapsing("Sheen", |ui| {
+        ui.add(egui::Slider::new(&mut material.sheen, 0.0..=1.0).text("Weight"));
+        ui.add(egui::Slider::new(&mut material.sheen_tint, 0.0..=1.0).text("Tint"));
+    });
+
+    ui.collapsing("Transmission", |ui| {
+        ui.add(egui::Slider::new(&mut material.transmission, 0.0..=1.0).text("Weight"));
+    });
+}
+
+// Thin film interference, like on soap bubbles, oil slicks or anodized metal
+fn thin_film_editor(ui: &mut Ui, material: &mut Material) {
+    ui.collapsing("Thin Film", |ui| {
+        ui.add(
+            egui::Slider::new(&mut material.film_thickness, 0.0..=2000.0).text("Thickness (nm)"),
+        );
+
+        if material.film_thickness > 0.0 && material.film_ior == 0.0 {
+            material.film_ior = DEFAULT_FILM_IOR;
+        }
+
+        ui.add(egui::Slider::new(&mut material.film_ior, 1.0..=3.0).text("Refractive Index"));
+        ui.add(egui::Slider::new(&mut material.film_variation, 0.0..=1.0).text("Variation"));
+
+        if material.film_variation > 0.0 && material.film_noise_frequency == 0.0 {
+            material.film_noise_frequency = DEFAULT_FILM_NOISE_FREQUENCY;
+        }
+
+        ui.add_enabled_ui(material.film_variation > 0.0, |ui| {
+            ui.add(
+                egui::Slider::new(&mut material.film_noise_frequency, 0.0..=100.0)
+                    .logarithmic(true)
+                    .text("Noise Frequency"),
+            );
+        });
+    });
+}
+
+fn medium_editor(ui: &mut Ui, medium: &mut Medium, density_grids: &DensityGrids) {
+    rgb_editor(ui, "Scattering", &mut medium.scattering);
+    rgb_editor(ui, "Absorption", &mut medium.absorption);
+
+    ui.add(egui::Slider::new(&mut medium.anisotropy, -0.99..=0.99).text("Anisotropy"));
+
+    egui::ComboBox::from_label("Density")
+        .selected_text(format!("{:?}", medium.density_type))
+        .show_ui(ui, |ui| {
+            let density_type = &mut medium.density_type;
+
+            ui.selectable_value(density_type, DensityType::Homogeneous, "Homogeneous");
+            ui.selectable_value(density_type, DensityType::Noise, "Noise");
+
+            ui.add_enabled_ui(!density_grids.grids.is_empty(), |ui| {
+                ui.selectable_value(density_type, DensityType::Grid, "Grid")
+                    .on_disabled_hover_text("No density grids were found in assets/volumes");
+            });
+        });
+
+    if medium.density_type == DensityType::Grid {
+        // default to the first grid
+        if medium.grid_index == 0 {
+            medium.grid_index = 1;
+        }
+
+        egui::ComboBox::from_label("Grid")
+            .selected_text(
+                density_grids
+                    .get(medium.grid_index)
+                    .map_or("", |grid| grid.name.as_str()),
+            )
+            .show_ui(ui, |ui| {
+                for (index, grid) in density_grids.grids.iter().enumerate() {
+                    // 1 more, for the null element at the start of the GPU buffer
+                    ui.selectable_value(&mut medium.grid_index, index as u32 + 1, &grid.name);
+                }
+            });
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Density Scale");
+        ui.add(
+            DragValue::new(&mut medium.density_scale)
+                .fixed_decimals(2)
+                .speed(0.01)
+                .range(0.0..=f32::INFINITY),
+        );
+    });
+
+    if medium.density_type == DensityType::Noise {
+        ui.horizontal(|ui| {
+            ui.label("Noise Frequency");
+            ui.add(
+                DragValue::new(&mut medium.noise_frequency)
+                    .fixed_decimals(2)
+                    .speed(0.01)
+                    .range(0.01..=f32::INFINITY),
+            );
+        });
+    }
+}
+
 #[derive(Resource, Default)]
 pub struct Menu {
     pub settings: Settings,
@@ -57,22 +431,45 @@ impl Menu {
         fps_counter: Res<FpsCounter>,
         camera: Res<Camera>,
         mut objects: ResMut<Objects>,
+        mut atmosphere: ResMut<Atmosphere>,
+        spectrum_binding: Res<SpectrumBinding>,
+        atmosphere_binding: Res<AtmosphereBinding>,
+        object_binding: Res<ObjectBinding>,
         profiler: Res<RenderProfiler>,
+        accumulation: Res<Accumulation>,
+        render_resolution: Res<RenderResolution>,
         mut resize_events: EventWriter<MenuResizeEvent>,
+        mut export_events: EventWriter<ExportEvent>,
     ) {
         let mut left_panel_size = 0.0;
         let mut right_panel_size = 0.0;
 
+        let previous_settings = menu.settings.clone();
+
         // Skip drawing the menu if fullscreen is enabled
         if !menu.settings.fullscreen {
             // Left panel is the control panel
-            left_panel_size = menu.control_panel(egui_render_state.context(), &mut objects);
+            let export;
+            (left_panel_size, export) = menu.control_panel(
+                egui_render_state.context(),
+                &mut objects,
+                &mut atmosphere,
+                &spectrum_binding.atlas,
+                &atmosphere_binding,
+                &object_binding,
+            );
+
+            if export {
+                export_events.write(ExportEvent);
+            }
 
             // Right panel is the debug panel
             right_panel_size = menu.debug_panel(
                 egui_render_state.context(),
                 &fps_counter,
                 &profiler,
+                &accumulation,
+                &render_resolution,
                 &camera,
                 &objects,
             );
@@ -90,6 +487,7 @@ impl Menu {
         // if it's changed, send a resize event
         if renderer_viewport.start != renderer_viewport_start
             || renderer_viewport.end != renderer_viewport_end
+            || menu.settings.changes_render_resolution(&previous_settings)
         {
             resize_events.write(MenuResizeEvent);
         }
@@ -99,9 +497,23 @@ impl Menu {
     }
 
     // We need to take objects as a ResMut<> to preserve change detection
-    // returns the size of the control panel
-    fn control_panel(&mut self, ctx: &egui::Context, objects: &mut ResMut<Objects>) -> f32 {
+    // returns the size of the control panel, and whether an export was requested
+    fn control_panel(
+        &mut self,
+        ctx: &egui::Context,
+        objects: &mut ResMut<Objects>,
+        atmosphere: &mut ResMut<Atmosphere>,
+        atlas: &SpectralAtlas,
+        atmosphere_binding: &AtmosphereBinding,
+        object_binding: &ObjectBinding,
+    ) -> (f32, bool) {
+        let compositions = &atmosphere_binding.compositions;
+        let environment_maps = &atmosphere_binding.environment_maps;
+        let density_grids = &object_binding.density_grids;
+        let image_textures = &object_binding.image_textures;
+
         let mut panel_size = 0.0;
+        let mut export = false;
 
         egui::SidePanel::left("control_panel")
             .resizable(true)
@@ -124,7 +536,372 @@ impl Menu {
 
                 ui.heading("Path tracing");
                 ui.checkbox(&mut self.settings.accumulate, "Enable accumulation");
-                ui.checkbox(&mut self.settings.spectral, "Spectral rendering");
+                ui.checkbox(&mut self.settings.spectral, "Spectral rendering")
+                    .on_hover_text("Sample wavelengths per path, RGB radiance is transported when off");
+
+                ui.add_enabled_ui(self.settings.spectral, |ui| {
+                    ui.checkbox(&mut self.settings.hero_wavelengths, "Hero wavelengths")
+                        .on_hover_text("Trace 4 stratified wavelengths per path instead of 1, reducing color noise");
+
+                    egui::ComboBox::from_label("Wavelengths")
+                        .selected_text(format!("{:?}", self.settings.wavelength_sampling))
+                        .show_ui(ui, |ui| {
+                            let sampling = &mut self.settings.wavelength_sampling;
+
+                            ui.selectable_value(sampling, WavelengthSampling::Uniform, "Uniform");
+                            ui.selectable_value(sampling, WavelengthSampling::Visible, "Visible");
+                        })
+                        .response
+                        .on_hover_text("Visible samples wavelengths proportionally to the CIE observer");
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Target samples");
+                    ui.add(DragValue::new(&mut self.settings.target_samples).speed(1.0));
+                })
+                .response
+                .on_hover_text(
+                    "Accumulation stops after this many samples per pixel are dispatched, 0 means no limit. \
+                     Rejected samples count towards it.",
+                );
+
+                ui.horizontal(|ui| {
+                    ui.label("Frame time budget");
+                    ui.add(
+                        DragValue::new(&mut self.settings.frame_time_budget)
+                            .speed(0.1)
+                            .fixed_decimals(1)
+                            .range(0.0..=1000.0)
+                            .suffix(" ms"),
+                    );
+                })
+                .response
+                .on_hover_text(
+                    "Takes multiple samples per frame to fill up this much GPU time, 0 means one sample per frame",
+                );
+
+                ui.checkbox(&mut self.settings.reproject, "Reproject while moving")
+                    .on_hover_text("Keep the accumulated samples when the camera moves");
+
+                ui.add_enabled_ui(self.settings.reproject, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Max history");
+                        ui.add(
+                            DragValue::new(&mut self.settings.max_history_length)
+                                .speed(1.0)
+                                .range(1..=1024),
+                        );
+                    })
+                    .response
+                    .on_hover_text(
+                        "Samples per pixel kept when reprojecting, higher values are less noisy but smear more",
+                    );
+                });
+
+                let pause_label = if self.settings.paused {
+                    "Resume"
+                } else {
+                    "Pause"
+                };
+
+                if ui.button(pause_label).clicked() {
+                    self.settings.paused = !self.settings.paused;
+                }
+
+                ui.checkbox(&mut self.settings.clamp_indirect, "Clamp indirect light")
+                    .on_hover_text("Removes fireflies, at the cost of darkening bright indirect lighting");
+
+                ui.add_enabled_ui(self.settings.clamp_indirect, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Max luminance");
+                        ui.add(
+                            DragValue::new(&mut self.settings.indirect_clamp)
+                                .speed(0.1)
+                                .fixed_decimals(1)
+                                .range(0.1..=1000.0),
+                        );
+                    });
+                });
+
+                ui.collapsing("Path depth", |ui| {
+                    let pathtrace = &mut self.settings.pathtrace;
+
+                    ui.horizontal(|ui| {
+                        ui.label("Max depth");
+                        ui.add(
+                            DragValue::new(&mut pathtrace.max_depth)
+                                .speed(0.1)
+                                .range(1..=256),
+                        );
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Diffuse");
+                        ui.add(
+                            DragValue::new(&mut pathtrace.max_diffuse_depth)
+                                .speed(0.1)
+                                .range(0..=256),
+                        );
+                    })
+                    .response
+                    .on_hover_text("Maximum number of diffuse bounces");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Specular");
+                        ui.add(
+                            DragValue::new(&mut pathtrace.max_specular_depth)
+                                .speed(0.1)
+                                .range(0..=256),
+                        );
+                    })
+                    .response
+                    .on_hover_text("Maximum number of specular reflections");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Transmission");
+                        ui.add(
+                            DragValue::new(&mut pathtrace.max_transmission_depth)
+                                .speed(0.1)
+                                .range(0..=256),
+                        );
+                    })
+                    .response
+                    .on_hover_text("Maximum number of refractions");
+
+                    egui::ComboBox::from_label("Russian roulette")
+                        .selected_text(format!("{:?}", pathtrace.roulette_strategy))
+                        .show_ui(ui, |ui| {
+                            let strategy = &mut pathtrace.roulette_strategy;
+
+                            ui.selectable_value(strategy, RouletteStrategy::Disabled, "Disabled");
+                            ui.selectable_value(strategy, RouletteStrategy::Throughput, "Throughput");
+                            ui.selectable_value(strategy, RouletteStrategy::Fixed, "Fixed");
+                        });
+
+                    ui.add_enabled_ui(
+                        pathtrace.roulette_strategy != RouletteStrategy::Disabled,
+                        |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Min depth");
+                                ui.add(
+                                    DragValue::new(&mut pathtrace.min_roulette_depth)
+                                        .speed(0.1)
+                                        .range(0..=256),
+                                );
+                            })
+                            .response
+                            .on_hover_text("Number of bounces before russian roulette starts");
+                        },
+                    );
+
+                    if pathtrace.roulette_strategy == RouletteStrategy::Fixed {
+                        ui.add(
+                            egui::Slider::new(&mut pathtrace.survival_probability, 0.05..=1.0)
+                                .text("Survival"),
+                        );
+                    }
+                });
+
+                egui::ComboBox::from_label("Debug mode")
+                    .selected_text(format!("{:?}", self.settings.pathtrace_debug))
+                    .show_ui(ui, |ui| {
+                        for debug_mode in PathtraceDebugMode::ALL {
+                            ui.selectable_value(
+                                &mut self.settings.pathtrace_debug,
+                                debug_mode,
+                                format!("{:?}", debug_mode),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text("Render a visualization of the scene instead of the path traced image");
+
+                ui.separator();
+
+                ui.heading("Denoiser");
+                ui.checkbox(&mut self.settings.denoiser.enabled, "Enable denoiser");
+
+                ui.add_enabled_ui(self.settings.denoiser.enabled, |ui| {
+                    egui::ComboBox::from_label("Show")
+                        .selected_text(format!("{:?}", self.settings.display_view))
+                        .show_ui(ui, |ui| {
+                            let view = &mut self.settings.display_view;
+
+                            ui.selectable_value(view, DisplayView::Denoised, "Denoised");
+                            ui.selectable_value(view, DisplayView::Raw, "Raw");
+                            ui.selectable_value(view, DisplayView::Split, "Split");
+                        });
+
+                    if self.settings.display_view == DisplayView::Split {
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.split_position, 0.0..=1.0)
+                                .text("Split"),
+                        )
+                        .on_hover_text("Raw on the left, denoised on the right");
+                    }
+
+                    ui.collapsing("Parameters", |ui| {
+                        let denoiser = &mut self.settings.denoiser;
+
+                        ui.horizontal(|ui| {
+                            ui.label("Iterations");
+                            ui.add(
+                                DragValue::new(&mut denoiser.atrous_iterations)
+                                    .speed(0.1)
+                                    .range(1..=MAX_ATROUS_ITERATIONS),
+                            );
+                        })
+                        .response
+                        .on_hover_text("Number of à-trous filter iterations, each one doubles the filter radius");
+
+                        ui.horizontal(|ui| {
+                            ui.label("Temporal alpha");
+                            ui.add(
+                                DragValue::new(&mut denoiser.temporal_alpha)
+                                    .speed(0.01)
+                                    .fixed_decimals(2)
+                                    .range(0.01..=1.0),
+                            );
+                        })
+                        .response
+                        .on_hover_text("Minimum weight of the current frame, lower values keep more history while moving");
+
+                        ui.horizontal(|ui| {
+                            ui.label("Color phi");
+                            ui.add(
+                                DragValue::new(&mut denoiser.color_phi)
+                                    .speed(0.1)
+                                    .fixed_decimals(1)
+                                    .range(0.1..=64.0),
+                            );
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Normal phi");
+                            ui.add(
+                                DragValue::new(&mut denoiser.normal_phi)
+                                    .speed(1.0)
+                                    .range(1.0..=512.0),
+                            );
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Depth phi");
+                            ui.add(
+                                DragValue::new(&mut denoiser.depth_phi)
+                                    .speed(0.1)
+                                    .fixed_decimals(1)
+                                    .range(0.1..=64.0),
+                            );
+                        });
+                    });
+                });
+
+                ui.separator();
+
+                ui.heading("Atmosphere");
+
+                egui::ComboBox::from_label("Sky")
+                    .selected_text(format!("{:?}", self.settings.pathtrace.sky_mode))
+                    .show_ui(ui, |ui| {
+                        let sky_mode = &mut self.settings.pathtrace.sky_mode;
+
+                        ui.selectable_value(sky_mode, SkyMode::PathTraced, "PathTraced");
+                        ui.selectable_value(sky_mode, SkyMode::Precomputed, "Precomputed");
+                        ui.selectable_value(sky_mode, SkyMode::Environment, "Environment");
+                    })
+                    .response
+                    .on_hover_text(
+                        "Path trace the atmosphere for reference renders, look the sky up in tables \
+                         precomputed whenever the atmosphere changes, or light the scene with an environment map \
+                         instead",
+                    );
+
+                if self.settings.pathtrace.sky_mode == SkyMode::Environment {
+                    environment_editor(ui, &mut self.settings.pathtrace, environment_maps);
+                } else {
+                    // Edit a copy, so the atmosphere is only marked as changed (resetting accumulation) if it
+                    // actually is
+                    let mut edited = (**atmosphere).clone();
+                    atmosphere_editor(ui, &mut edited, compositions);
+                    atmosphere.set_if_neq(edited);
+                }
+
+                ui.separator();
+
+                ui.heading("Resolution");
+
+                egui::ComboBox::from_label("Mode")
+                    .selected_text(format!("{:?}", self.settings.resolution_mode))
+                    .show_ui(ui, |ui| {
+                        let mode = &mut self.settings.resolution_mode;
+
+                        ui.selectable_value(mode, ResolutionMode::Scaled, "Scaled");
+                        ui.selectable_value(mode, ResolutionMode::Fixed, "Fixed");
+                    });
+
+                match self.settings.resolution_mode {
+                    ResolutionMode::Scaled => {
+                        ui.horizontal(|ui| {
+                            ui.label("Render scale");
+                            ui.add(
+                                DragValue::new(&mut self.settings.render_scale)
+                                    .speed(1.0)
+                                    .range(25..=200)
+                                    .suffix("%"),
+                            );
+                        })
+                        .response
+                        .on_hover_text("Percentage of the window size to render at");
+                    }
+                    ResolutionMode::Fixed => {
+                        ui.horizontal(|ui| {
+                            ui.label("Size");
+                            ui.add(
+                                DragValue::new(&mut self.settings.fixed_resolution.x)
+                                    .speed(1.0)
+                                    .range(FIXED_RESOLUTION_RANGE),
+                            );
+                            ui.label("x");
+                            ui.add(
+                                DragValue::new(&mut self.settings.fixed_resolution.y)
+                                    .speed(1.0)
+                                    .range(FIXED_RESOLUTION_RANGE),
+                            );
+                        })
+                        .response
+                        .on_hover_text(
+                            "Sizes of more than 4096x4096 pixels are scaled down to that many pixels",
+                        );
+                    }
+                }
+
+                egui::ComboBox::from_label("Debug view")
+                    .selected_text(format!("{:?}", self.settings.debug_view))
+                    .show_ui(ui, |ui| {
+                        for debug_view in DebugView::ALL {
+                            ui.selectable_value(
+                                &mut self.settings.debug_view,
+                                debug_view,
+                                format!("{:?}", debug_view),
+                            );
+                        }
+                    });
+
+                egui::ComboBox::from_label("Display filter")
+                    .selected_text(format!("{:?}", self.settings.display_filter))
+                    .show_ui(ui, |ui| {
+                        let filter = &mut self.settings.display_filter;
+
+                        ui.selectable_value(filter, DisplayFilter::Nearest, "Nearest");
+                        ui.selectable_value(filter, DisplayFilter::Bilinear, "Bilinear");
+                        ui.selectable_value(filter, DisplayFilter::Lanczos, "Lanczos");
+                    });
+
+                export = ui
+                    .button("Export")
+                    .on_hover_text("Write the render and its output buffers to PFM files in renders/")
+                    .clicked();
 
                 ui.separator();
 
@@ -140,6 +917,23 @@ impl Menu {
                     });
                     material.albedo = albedo.into();
 
+                    if matches!(
+                        material.ty,
+                        MaterialType::Lambertian
+                            | MaterialType::Metal
+                            | MaterialType::Subsurface
+                            | MaterialType::Principled
+                    ) {
+                        ui.label("Reflectance Spectrum");
+                        spectrum_selector(
+                            ui,
+                            "reflectance_spectrum",
+                            &mut material.reflectance_spectrum,
+                            atlas,
+                            SpectrumKind::Reflectance,
+                        );
+                    }
+
                     ui.horizontal(|ui| {
                         ui.label("Emission");
                         ui.add(
@@ -150,6 +944,53 @@ impl Menu {
                         );
                     });
 
+                    // Blackbody emission is chosen in the same list as the tabulated illuminants
+                    ui.label("Emission Spectrum");
+                    let blackbody =
+                        material.emission_spectrum == 0 && material.blackbody_temperature > 0.0;
+
+                    let selected_text = match atlas.name(material.emission_spectrum) {
+                        Some(name) => name,
+                        None if blackbody => "Blackbody",
+                        None => "RGB",
+                    };
+
+                    let rgb = material.emission_spectrum == 0 && !blackbody;
+
+                    egui::ComboBox::from_id_salt("emission_spectrum")
+                        .selected_text(selected_text)
+                        .show_ui(ui, |ui| {
+                            if ui.selectable_label(rgb, "RGB").clicked() {
+                                material.emission_spectrum = 0;
+                                material.blackbody_temperature = 0.0;
+                            }
+
+                            if ui.selectable_label(blackbody, "Blackbody").clicked() {
+                                material.emission_spectrum = 0;
+                                material.blackbody_temperature = DEFAULT_BLACKBODY_TEMPERATURE;
+                            }
+
+                            for (index, spectrum) in atlas.of_kind(SpectrumKind::Illuminant) {
+                                let selected = material.emission_spectrum == index;
+
+                                if ui.selectable_label(selected, &spectrum.name).clicked() {
+                                    material.emission_spectrum = index;
+                                    material.blackbody_temperature = 0.0;
+                                }
+                            }
+                        });
+
+                    if blackbody {
+                        ui.horizontal(|ui| {
+                            ui.label("Temperature (K)");
+                            ui.add(
+                                DragValue::new(&mut material.blackbody_temperature)
+                                    .speed(10.0)
+                                    .range(1000.0..=20000.0),
+                            );
+                        });
+                    }
+
                     ui.label("Material Type");
                     egui::ComboBox::from_label("")
                         .selected_text(format!("{:?}", material.ty))
@@ -167,6 +1008,29 @@ impl Menu {
                                 MaterialType::Dielectric,
                                 "Dielectric",
                             );
+                            ui.selectable_value(&mut material.ty, MaterialType::Volume, "Volume");
+
+                            let subsurface = ui.selectable_value(
+                                &mut material.ty,
+                                MaterialType::Subsurface,
+                                "Subsurface",
+                            );
+
+                            if subsurface.clicked() && material.mean_free_path == Vec3::ZERO {
+                                material.mean_free_path = DEFAULT_MEAN_FREE_PATH;
+                            }
+
+                            let principled = ui.selectable_value(
+                                &mut material.ty,
+                                MaterialType::Principled,
+                                "Principled",
+                            );
+
+                            // without any specular the dielectric base would only be diffuse
+                            if principled.clicked() && material.specular == 0.0 {
+                                material.specular = 1.0;
+                                material.ior = material.ior.max(1.5);
+                            }
                         });
 
                     let show_roughness = matches!(
@@ -174,8 +1038,10 @@ impl Menu {
                         MaterialType::Metal | MaterialType::Dielectric
                     );
 
-                    let show_ior =
-                        matches!(self.object_editor.material.ty, MaterialType::Dielectric);
+                    let show_ior = matches!(
+                        self.object_editor.material.ty,
+                        MaterialType::Dielectric | MaterialType::Subsurface
+                    );
 
                     if show_roughness {
                         ui.label("Roughness");
@@ -185,6 +1051,17 @@ impl Menu {
                         ));
                     }
 
+                    if matches!(self.object_editor.material.ty, MaterialType::Metal) {
+                        ui.label("Complex Refractive Index");
+                        spectrum_selector(
+                            ui,
+                            "ior_spectrum",
+                            &mut self.object_editor.material.ior_spectrum,
+                            atlas,
+                            SpectrumKind::Conductor,
+                        );
+                    }
+
                     if show_ior {
                         ui.label("Refractive Index");
                         ui.add(egui::Slider::new(
@@ -192,6 +1069,55 @@ impl Menu {
                             0.0..=4.0,
                         ));
                     }
+
+                    if matches!(self.object_editor.material.ty, MaterialType::Principled) {
+                        principled_editor(ui, &mut self.object_editor.material);
+                    }
+
+                    if matches!(
+                        self.object_editor.material.ty,
+                        MaterialType::Metal | MaterialType::Dielectric | MaterialType::Principled
+                    ) {
+                        thin_film_editor(ui, &mut self.object_editor.material);
+                    }
+
+                    if !matches!(self.object_editor.material.ty, MaterialType::Volume) {
+                        texture_slots_editor(ui, &mut self.object_editor.material, image_textures);
+                    }
+
+                    if matches!(self.object_editor.material.ty, MaterialType::Subsurface) {
+                        rgb_editor(
+                            ui,
+                            "Mean Free Path",
+                            &mut self.object_editor.material.mean_free_path,
+                        );
+                    }
+
+                    if matches!(self.object_editor.material.ty, MaterialType::Volume) {
+                        ui.label("Medium");
+                        medium_editor(ui, &mut self.object_editor.medium, density_grids);
+
+                        let medium = &self.object_editor.medium;
+                        let grid = density_grids
+                            .get(medium.grid_index)
+                            .filter(|_| medium.density_type == DensityType::Grid);
+
+                        if let Some(grid) = grid {
+                            let fit = ui
+                                .button("Fit Box to Grid")
+                                .on_hover_text("Size the box to the grid at its voxel size, around the center of the box");
+
+                            if fit.clicked() {
+                                let aabb = &mut self.object_editor.aabb;
+                                let center = (aabb.min + aabb.max) / 2.0;
+
+                                aabb.min = center - grid.extent() / 2.0;
+                                aabb.max = center + grid.extent() / 2.0;
+
+                                self.object_editor.geometry_type = GeometryType::Aabb;
+                            }
+                        }
+                    }
                 });
 
                 ui.collapsing("Geometry", |ui| {
@@ -240,12 +1166,35 @@ impl Menu {
                             vec3_editor(&mut triangle.a, ui);
                             vec3_editor(&mut triangle.b, ui);
                             vec3_editor(&mut triangle.c, ui);
+
+                            ui.label("Texture Coordinates");
+                            uv_editor(&mut triangle.uv_a, ui);
+                            uv_editor(&mut triangle.uv_b, ui);
+                            uv_editor(&mut triangle.uv_c, ui);
                         }
                     }
                 });
 
                 ui.horizontal(|ui| {
                     if ui.button("Create").clicked() {
+                        let material = &mut self.object_editor.material;
+
+                        material.medium_index = if material.ty == MaterialType::Volume {
+                            let reuse_medium = objects
+                                .media
+                                .last()
+                                .is_some_and(|&prev| prev == self.object_editor.medium);
+
+                            if !reuse_medium {
+                                objects.media.push(self.object_editor.medium);
+                            }
+
+                            // Like the material index, the GPU buffer starts with a null element
+                            objects.media.len() as u32
+                        } else {
+                            0
+                        };
+
                         let reuse_material = objects
                             .materials
                             .last()
@@ -295,15 +1244,18 @@ impl Menu {
                 });
             });
 
-        panel_size
+        (panel_size, export)
     }
 
     // returns the size of the debug panel
+    #[allow(clippy::too_many_arguments)]
     fn debug_panel(
         &mut self,
         ctx: &egui::Context,
         fps_counter: &FpsCounter,
         profiler: &RenderProfiler,
+        accumulation: &Accumulation,
+        render_resolution: &RenderResolution,
         camera: &Camera,
         objects: &Objects,
     ) -> f32 {
@@ -319,6 +1271,10 @@ impl Menu {
                 ui.heading("Frametimes");
 
                 ui.label(format!("FPS: {:.1}", fps_counter.average_fps()));
+                ui.label(format!(
+                    "Render resolution: {}x{}",
+                    render_resolution.width, render_resolution.height
+                ));
 
                 ui.label("\nPasses:");
                 for (name, time) in profiler.times.iter() {
@@ -331,6 +1287,37 @@ impl Menu {
 
                 ui.separator();
 
+                ui.heading("Accumulation");
+
+                if self.settings.target_samples > 0 {
+                    ui.label(format!(
+                        "Samples: {} / {}",
+                        accumulation.sample_count, self.settings.target_samples
+                    ));
+                } else {
+                    ui.label(format!("Samples: {}", accumulation.sample_count));
+                }
+
+                ui.label(format!(
+                    "Samples per frame: {}",
+                    accumulation.samples_per_frame
+                ));
+
+                ui.label(format!(
+                    "Rejected samples: {} / frame",
+                    accumulation.rejected_samples
+                ))
+                .on_hover_text("Samples thrown away for being NaN or infinite");
+
+                match accumulation.eta(self, fps_counter) {
+                    Some(eta) if eta.is_zero() => ui.label("Done"),
+                    Some(eta) => ui.label(format!("ETA: {:.1} s", eta.as_secs_f64())),
+                    None if self.settings.paused => ui.label("Paused"),
+                    None => ui.label("ETA: -"),
+                };
+
+                ui.separator();
+
                 ui.heading("Camera");
 
                 ui.label(format!(
@@ -354,6 +1341,7 @@ impl Menu {
                 ui.label(format!("Sphere count: {}", objects.spheres.len()));
                 ui.label(format!("AABB count: {}", objects.aabbs.len()));
                 ui.label(format!("Triangle count: {}", objects.triangles.len()));
+                ui.label(format!("Medium count: {}", objects.media.len()));
 
                 ui.separator();
 
@@ -371,10 +1359,71 @@ impl Menu {
     }
 }
 
+#[derive(Clone)]
 pub struct Settings {
     pub fullscreen: bool,
     pub accumulate: bool,
     pub spectral: bool,
+    pub wavelength_sampling: WavelengthSampling,
+    // Whether every path carries several wavelengths instead of one
+    pub hero_wavelengths: bool,
+
+    // Accumulation stops after dispatching this many samples per pixel, 0 means no limit. Rejected NaN or infinite
+    // samples count towards it, so pixels that had some end up with fewer accumulated samples.
+    pub target_samples: u32,
+    pub paused: bool,
+
+    // Per-frame GPU time in milliseconds to fill with samples, 0 means one sample per frame
+    pub frame_time_budget: f32,
+
+    // Whether to reproject the accumulated samples when the camera moves instead of starting over
+    pub reproject: bool,
+    // Samples per pixel kept when reprojecting
+    pub max_history_length: u32,
+
+    // Whether to clamp the luminance of the indirect lighting of each sample to `indirect_clamp`
+    pub clamp_indirect: bool,
+    pub indirect_clamp: f32,
+
+    pub pathtrace: PathtraceSettings,
+
+    pub resolution_mode: ResolutionMode,
+    // Percentage of the window size, used in scaled mode
+    pub render_scale: u32,
+    // Used in fixed mode
+    pub fixed_resolution: UVec2,
+    // Filter used to scale the rendered image to the viewport
+    pub display_filter: DisplayFilter,
+
+    pub denoiser: DenoiserSettings,
+    pub display_view: DisplayView,
+    // Horizontal position of the split in the split view, from 0 to 1
+    pub split_position: f32,
+
+    pub debug_view: DebugView,
+    pub pathtrace_debug: PathtraceDebugMode,
+}
+
+impl Settings {
+    /// Whether going from `previous` to these settings changes the rendered image, so the samples accumulated
+    /// so far can't be reused.
+    pub fn invalidates_accumulation(&self, previous: &Settings) -> bool {
+        self.accumulate != previous.accumulate
+            || self.spectral != previous.spectral
+            || self.wavelength_sampling != previous.wavelength_sampling
+            || self.hero_wavelengths != previous.hero_wavelengths
+            || self.pathtrace_debug != previous.pathtrace_debug
+            || self.pathtrace != previous.pathtrace
+            || self.clamp_indirect != previous.clamp_indirect
+            || (self.clamp_indirect && self.indirect_clamp != previous.indirect_clamp)
+    }
+
+    /// Whether going from `previous` to these settings changes the render resolution.
+    pub fn changes_render_resolution(&self, previous: &Settings) -> bool {
+        self.resolution_mode != previous.resolution_mode
+            || self.render_scale != previous.render_scale
+            || self.fixed_resolution != previous.fixed_resolution
+    }
 }
 
 impl Default for Settings {
@@ -383,6 +1432,25 @@ impl Default for Settings {
             fullscreen: false,
             accumulate: true,
             spectral: true,
+            wavelength_sampling: WavelengthSampling::Visible,
+            hero_wavelengths: true,
+            target_samples: 0,
+            paused: false,
+            frame_time_budget: 0.0,
+            reproject: true,
+            max_history_length: 16,
+            clamp_indirect: false,
+            indirect_clamp: 10.0,
+            pathtrace: PathtraceSettings::default(),
+            resolution_mode: ResolutionMode::Scaled,
+            render_scale: 100,
+            fixed_resolution: UVec2::new(1920, 1080),
+            display_filter: DisplayFilter::Bilinear,
+            denoiser: DenoiserSettings::default(),
+            display_view: DisplayView::Denoised,
+            split_position: 0.5,
+            debug_view: DebugView::None,
+            pathtrace_debug: PathtraceDebugMode::None,
         }
     }
 }
@@ -402,4 +1470,5 @@ struct ObjectEditor {
     pub sphere: Sphere,
     pub aabb: Aabb,
     pub triangle: Triangle,
+    pub medium: Medium,
 }
@@ -21,6 +21,7 @@ use crate::{
     wgpu_limits, WGPU_FEATURES,
 };
 
+pub mod atmosphere;
 pub mod camera;
 pub mod control;
 pub mod events;
@@ -30,6 +31,7 @@ pub mod lookup;
 pub mod menu;
 pub mod object;
 pub mod renderer;
+pub mod spectral_data;
 pub mod time;
 
 pub fn run() {
@@ -7,7 +7,10 @@ use gpu_bytes::AsStd430;
 use wgpu::util::DeviceExt;
 
 use crate::app::{
-    object::{Aabb, Material, Sphere, Triangle},
+    object::{
+        density_grid::DensityGrids, texture::ImageTextures, Aabb, Material, Medium, Sphere,
+        Triangle,
+    },
     renderer::SurfaceState,
 };
 
@@ -19,6 +22,16 @@ pub struct ObjectBinding {
     pub spheres_buffer: wgpu::Buffer,
     pub aabbs_buffer: wgpu::Buffer,
     pub triangles_buffer: wgpu::Buffer,
+    pub media_buffer: wgpu::Buffer,
+
+    // Density grids don't change after loading, so their buffers are only created once
+    pub density_grids: DensityGrids,
+    pub density_grid_infos_buffer: wgpu::Buffer,
+    pub densities_buffer: wgpu::Buffer,
+    pub majorants_buffer: wgpu::Buffer,
+
+    // Material textures don't change after loading either, and are in a bind group of their own
+    pub image_textures: ImageTextures,
 
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
@@ -42,11 +55,31 @@ impl ObjectBinding {
         let spheres = vec![Sphere::null()];
         let aabbs = vec![Aabb::null()];
         let triangles = vec![Triangle::null()];
+        let media = vec![Medium::null()];
 
         let materials_buffer = create_buffer("materials_buffer", materials.as_std430().as_slice());
         let spheres_buffer = create_buffer("spheres_buffer", spheres.as_std430().as_slice());
         let aabbs_buffer = create_buffer("aabbs_buffer", aabbs.as_std430().as_slice());
         let triangles_buffer = create_buffer("triangles_buffer", triangles.as_std430().as_slice());
+        let media_buffer = create_buffer("media_buffer", media.as_std430().as_slice());
+
+        let density_grids = DensityGrids::load(&surface_state.gpu.device);
+
+        let density_grid_infos_buffer = create_buffer(
+            "density_grid_infos_buffer",
+            density_grids.infos().as_std430().as_slice(),
+        );
+        let densities_buffer = create_buffer(
+            "densities_buffer",
+            bytemuck::cast_slice(&density_grids.densities()),
+        );
+        let majorants_buffer = create_buffer(
+            "majorants_buffer",
+            bytemuck::cast_slice(&density_grids.majorants()),
+        );
+
+        let image_textures =
+            ImageTextures::load(&surface_state.gpu.device, &surface_state.gpu.queue);
 
         let (bind_group_layout, bind_group) = wgputil::binding::create_sequential_linked(
             &surface_state.gpu.device,
@@ -56,6 +89,10 @@ impl ObjectBinding {
                 wgputil::binding::bind_buffer_storage(&spheres_buffer, true),
                 wgputil::binding::bind_buffer_storage(&aabbs_buffer, true),
                 wgputil::binding::bind_buffer_storage(&triangles_buffer, true),
+                wgputil::binding::bind_buffer_storage(&media_buffer, true),
+                wgputil::binding::bind_buffer_storage(&density_grid_infos_buffer, true),
+                wgputil::binding::bind_buffer_storage(&densities_buffer, true),
+                wgputil::binding::bind_buffer_storage(&majorants_buffer, true),
             ],
         );
 
@@ -64,6 +101,12 @@ impl ObjectBinding {
             spheres_buffer,
             aabbs_buffer,
             triangles_buffer,
+            media_buffer,
+            density_grids,
+            density_grid_infos_buffer,
+            densities_buffer,
+            majorants_buffer,
+            image_textures,
             bind_group_layout,
             bind_group,
         };
@@ -94,6 +137,9 @@ impl ObjectBinding {
         let mut triangles = vec![Triangle::null()];
         triangles.extend_from_slice(&objects.triangles);
 
+        let mut media = vec![Medium::null()];
+        media.extend_from_slice(&objects.media);
+
         let usage = wgpu::BufferUsages::STORAGE;
 
         let device = &surface_state.gpu.device;
@@ -125,6 +171,13 @@ impl ObjectBinding {
                 usage,
             });
 
+        object_binding.media_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("media_buffer"),
+                contents: media.as_std430().as_slice(),
+                usage,
+            });
+
         object_binding.bind_group = wgputil::binding::create_sequential_with_layout(
             device,
             "object_binding",
@@ -134,6 +187,10 @@ impl ObjectBinding {
                 object_binding.spheres_buffer.as_entire_binding(),
                 object_binding.aabbs_buffer.as_entire_binding(),
                 object_binding.triangles_buffer.as_entire_binding(),
+                object_binding.media_buffer.as_entire_binding(),
+                object_binding.density_grid_infos_buffer.as_entire_binding(),
+                object_binding.densities_buffer.as_entire_binding(),
+                object_binding.majorants_buffer.as_entire_binding(),
             ],
         );
     }
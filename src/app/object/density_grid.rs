@@ -0,0 +1,340 @@
+use std::{fs, io, path::Path};
+
+use glam::{UVec3, Vec3};
+use gpu_bytes_derive::AsStd430;
+
+use crate::{app::spectral_data, util};
+
+/*
+***Density grid format***
+
+Density grids are loaded from the .grid files in assets/volumes, which the `density_grid` tool converts raw voxel
+dumps into. All values are little-endian:
+    - the magic bytes `GRID`
+    - the format version as a u32, currently 1
+    - the width, height and depth of the grid in voxels as u32s
+    - the size of a voxel in world units as an f32
+    - width * height * depth densities as f32s, with x changing fastest and z slowest
+*/
+
+pub const GRID_MAGIC: &[u8; 4] = b"GRID";
+pub const GRID_VERSION: u32 = 1;
+
+// Size of the header in bytes
+const HEADER_SIZE: usize = 24;
+
+// Each majorant bounds the density of a block of this many voxels along each axis
+pub const MAJORANT_BLOCK_SIZE: u32 = 8;
+
+pub struct DensityGrid {
+    // File name without the extension
+    pub name: String,
+    pub size: UVec3,
+    pub voxel_size: f32,
+    pub densities: Vec<f32>,
+}
+
+impl DensityGrid {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(path, &fs::read(path)?)
+    }
+
+    // Reads a grid in the format above, `path` is only used for the name and errors
+    fn parse(path: &Path, bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != GRID_MAGIC {
+            return Err(spectral_data::invalid_data(
+                path,
+                "isn't a density grid".to_owned(),
+            ));
+        }
+
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+        let version = read_u32(4);
+
+        if version != GRID_VERSION {
+            return Err(spectral_data::invalid_data(
+                path,
+                format!("has unsupported version {}", version),
+            ));
+        }
+
+        let size = UVec3::new(read_u32(8), read_u32(12), read_u32(16));
+        let voxel_size = f32::from_le_bytes(bytes[20..24].try_into().unwrap());
+
+        let voxel_count = size.x as usize * size.y as usize * size.z as usize;
+
+        if voxel_count == 0 || bytes.len() != HEADER_SIZE + voxel_count * 4 {
+            return Err(spectral_data::invalid_data(
+                path,
+                format!("doesn't hold the {} voxels of a {} grid", voxel_count, size),
+            ));
+        }
+
+        if !voxel_size.is_finite() || voxel_size <= 0.0 {
+            return Err(spectral_data::invalid_data(
+                path,
+                "needs a finite voxel size above 0".to_owned(),
+            ));
+        }
+
+        let densities = bytes[HEADER_SIZE..]
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+            .map(|density| {
+                // like the converter does, so a stray NaN or infinity can't break the majorants
+                if density.is_finite() {
+                    density.max(0.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        let name = path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(Self {
+            name,
+            size,
+            voxel_size,
+            densities,
+        })
+    }
+
+    // Size of the grid in world units
+    pub fn extent(&self) -> Vec3 {
+        self.size.as_vec3() * self.voxel_size
+    }
+
+    pub fn majorant_size(&self) -> UVec3 {
+        (self.size + UVec3::splat(MAJORANT_BLOCK_SIZE - 1)) / MAJORANT_BLOCK_SIZE
+    }
+
+    fn density(&self, voxel: UVec3) -> f32 {
+        let index = voxel.x as usize
+            + self.size.x as usize * (voxel.y as usize + self.size.y as usize * voxel.z as usize);
+
+        self.densities[index]
+    }
+
+    // Maximum density of every block. The voxels bordering a block are included, since the density is interpolated
+    // between voxel centers.
+    pub fn majorants(&self) -> Vec<f32> {
+        let majorant_size = self.majorant_size();
+        let mut majorants = Vec::with_capacity(majorant_size.element_product() as usize);
+
+        for z in 0..majorant_size.z {
+            for y in 0..majorant_size.y {
+                for x in 0..majorant_size.x {
+                    let block = UVec3::new(x, y, z);
+
+                    let start = (block * MAJORANT_BLOCK_SIZE).saturating_sub(UVec3::ONE);
+                    let end =
+                        ((block + UVec3::ONE) * MAJORANT_BLOCK_SIZE).min(self.size - UVec3::ONE);
+
+                    let mut majorant: f32 = 0.0;
+
+                    for voxel_z in start.z..=end.z {
+                        for voxel_y in start.y..=end.y {
+                            for voxel_x in start.x..=end.x {
+                                majorant = majorant
+                                    .max(self.density(UVec3::new(voxel_x, voxel_y, voxel_z)));
+                            }
+                        }
+                    }
+
+                    majorants.push(majorant);
+                }
+            }
+        }
+
+        majorants
+    }
+}
+
+// Matches `DensityGridInfo` in medium.slang
+#[derive(AsStd430, Default, Clone, Copy, Debug)]
+pub struct DensityGridInfo {
+    pub size: UVec3,
+    // Index of the first density of the grid
+    pub offset: u32,
+
+    pub majorant_size: UVec3,
+    pub majorant_offset: u32,
+}
+
+/// Density grids loaded from assets/volumes. The densities and majorants of all grids are packed one after the
+/// other into a buffer each, and the first grid info is left empty like the null elements of the other object buffers.
+pub struct DensityGrids {
+    pub grids: Vec<DensityGrid>,
+}
+
+impl DensityGrids {
+    pub fn load(device: &wgpu::Device) -> Self {
+        let mut grids = Vec::new();
+
+        // volumes with a grid density just can't be created without any grids
+        let Ok(entries) = fs::read_dir(util::asset_path("volumes")) else {
+            return Self { grids };
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "grid")
+            })
+            .collect();
+
+        // keep indices stable between runs
+        paths.sort();
+
+        // The densities of all grids share one storage buffer, including the 0 appended to it. The majorants are
+        // always fewer, so their buffer fits if this one does.
+        let max_buffer_size = device.limits().max_storage_buffer_binding_size as u64;
+        let mut buffer_size = 4;
+
+        for path in paths {
+            let grid = DensityGrid::load(&path).and_then(|grid| {
+                let grid_size = grid.densities.len() as u64 * 4;
+
+                if buffer_size + grid_size > max_buffer_size {
+                    return Err(spectral_data::invalid_data(
+                        &path,
+                        format!(
+                            "needs {} bytes of densities, but only {} of the {} byte storage buffer limit are left",
+                            grid_size,
+                            max_buffer_size - buffer_size,
+                            max_buffer_size
+                        ),
+                    ));
+                }
+
+                Ok(grid)
+            });
+
+            match grid {
+                Ok(grid) => {
+                    buffer_size += grid.densities.len() as u64 * 4;
+                    grids.push(grid);
+                }
+                Err(err) => log::error!("Failed to load density grid: {}", err),
+            }
+        }
+
+        Self { grids }
+    }
+
+    // Takes the index into the GPU buffer, which is 1 more than the index into `grids`
+    pub fn get(&self, index: u32) -> Option<&DensityGrid> {
+        self.grids.get((index as usize).checked_sub(1)?)
+    }
+
+    // Including the empty first info
+    pub fn infos(&self) -> Vec<DensityGridInfo> {
+        let mut infos = vec![DensityGridInfo::default()];
+
+        let mut offset = 0;
+        let mut majorant_offset = 0;
+
+        for grid in &self.grids {
+            infos.push(DensityGridInfo {
+                size: grid.size,
+                offset,
+                majorant_size: grid.majorant_size(),
+                majorant_offset,
+            });
+
+            offset += grid.densities.len() as u32;
+            majorant_offset += grid.majorant_size().element_product();
+        }
+
+        infos
+    }
+
+    // A 0 is appended to both buffers, so they aren't empty without any grids
+    pub fn densities(&self) -> Vec<f32> {
+        let mut densities: Vec<f32> = self
+            .grids
+            .iter()
+            .flat_map(|grid| grid.densities.iter().copied())
+            .collect();
+
+        densities.push(0.0);
+        densities
+    }
+
+    pub fn majorants(&self) -> Vec<f32> {
+        let mut majorants: Vec<f32> = self
+            .grids
+            .iter()
+            .flat_map(|grid| grid.majorants())
+            .collect();
+
+        majorants.push(0.0);
+        majorants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_grid_loads_with_bounding_majorants() {
+        let grid = DensityGrid::load(&util::asset_path("volumes/cloud.grid")).unwrap();
+        assert_eq!(grid.size, UVec3::splat(32));
+
+        let majorant_size = grid.majorant_size();
+        let majorants = grid.majorants();
+
+        for z in 0..grid.size.z {
+            for y in 0..grid.size.y {
+                for x in 0..grid.size.x {
+                    let voxel = UVec3::new(x, y, z);
+                    let block = voxel / MAJORANT_BLOCK_SIZE;
+                    let index = block.x + majorant_size.x * (block.y + majorant_size.y * block.z);
+
+                    assert!(grid.density(voxel) <= majorants[index as usize]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn non_finite_and_negative_densities_load_as_zero() {
+        let values = [
+            1.0,
+            -1.0,
+            f32::NAN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            0.5,
+            2.0,
+            0.0,
+        ];
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(GRID_MAGIC);
+        bytes.extend_from_slice(&GRID_VERSION.to_le_bytes());
+
+        for extent in [2u32, 2, 2] {
+            bytes.extend_from_slice(&extent.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let grid = DensityGrid::parse(Path::new("test.grid"), &bytes).unwrap();
+
+        assert_eq!(grid.densities, [1.0, 0.0, 0.0, 0.0, 0.0, 0.5, 2.0, 0.0]);
+        assert_eq!(grid.majorants(), [2.0]);
+    }
+}
@@ -1,9 +1,11 @@
 use bevy_ecs::resource::Resource;
 use bevy_ecs::system::Commands;
-use glam::Vec3;
+use glam::{Vec2, Vec3};
 use gpu_bytes_derive::AsStd430;
 
 pub mod binding;
+pub mod density_grid;
+pub mod texture;
 
 #[derive(Resource)]
 pub struct Objects {
@@ -11,6 +13,7 @@ pub struct Objects {
     pub spheres: Vec<Sphere>,
     pub aabbs: Vec<Aabb>,
     pub triangles: Vec<Triangle>,
+    pub media: Vec<Medium>,
 }
 
 impl Objects {
@@ -19,12 +22,14 @@ impl Objects {
         let spheres = vec![];
         let aabbs = vec![];
         let triangles = vec![];
+        let media = vec![];
 
         commands.insert_resource(Objects {
             materials,
             spheres,
             aabbs,
             triangles,
+            media,
         });
     }
 }
@@ -36,6 +41,12 @@ pub enum MaterialType {
     Lambertian = 0,
     Metal = 1,
     Dielectric = 2,
+    // The bounds of a participating medium, the surface itself doesn't scatter
+    Volume = 3,
+    // Random walk subsurface scattering under a smooth dielectric boundary
+    Subsurface = 4,
+    // Layered material with a coat, metal, specular, sheen and transmission, like OpenPBR
+    Principled = 5,
 }
 
 impl gpu_bytes::AsStd430 for MaterialType {
@@ -51,6 +62,42 @@ pub struct Material {
     pub emission: f32,
     pub ior: f32,
     pub ty: MaterialType,
+
+    // Indices into the spectral atlas of `SpectrumBinding`, 0 means the RGB values above are used instead
+    pub reflectance_spectrum: u32,
+    pub emission_spectrum: u32,
+    pub ior_spectrum: u32,
+
+    // Emits blackbody radiation at this temperature in kelvin if above 0 and there's no emission spectrum
+    pub blackbody_temperature: f32,
+
+    // Index into `Objects::media` plus 1 for volumes, like the other indices into the GPU buffers
+    pub medium_index: u32,
+
+    // Average distance light travels between scattering events inside subsurface materials, for each RGB channel
+    pub mean_free_path: Vec3,
+
+    // Weights of the layers of principled materials, between 0 and 1
+    pub metalness: f32,
+    pub specular: f32,
+    pub coat: f32,
+    pub coat_roughness: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub transmission: f32,
+
+    // Thin film on top of metal, dielectric and principled materials, its thickness is in nm and there's none at 0
+    pub film_thickness: f32,
+    pub film_ior: f32,
+    // Fraction of the thickness it varies by with noise, and the frequency of the noise in features per unit
+    pub film_variation: f32,
+    pub film_noise_frequency: f32,
+
+    // Indices into `ImageTextures`, 0 means no texture
+    pub albedo_texture: u32,
+    pub roughness_texture: u32,
+    pub normal_texture: u32,
+    pub emission_texture: u32,
 }
 
 impl Material {
@@ -94,12 +141,32 @@ impl Aabb {
     }
 }
 
-#[derive(AsStd430, Default, Clone, Copy, Debug)]
+#[derive(AsStd430, Clone, Copy, Debug)]
 pub struct Triangle {
     pub a: Vec3,
     pub b: Vec3,
     pub c: Vec3,
     pub material_index: u32,
+
+    // Texture coordinates of each vertex
+    pub uv_a: Vec2,
+    pub uv_b: Vec2,
+    pub uv_c: Vec2,
+}
+
+impl Default for Triangle {
+    // Maps the triangle to the lower left half of a texture, so textures don't collapse to a single texel
+    fn default() -> Self {
+        Self {
+            a: Vec3::ZERO,
+            b: Vec3::ZERO,
+            c: Vec3::ZERO,
+            material_index: 0,
+            uv_a: Vec2::new(0.0, 1.0),
+            uv_b: Vec2::new(1.0, 1.0),
+            uv_c: Vec2::new(0.0, 0.0),
+        }
+    }
 }
 
 impl Triangle {
@@ -110,3 +177,65 @@ impl Triangle {
         }
     }
 }
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DensityType {
+    #[default]
+    Homogeneous = 0,
+    // Puffs of fractal value noise, like smoke
+    Noise = 1,
+    // A density grid from assets/volumes, stretched over the bounds of the volume
+    Grid = 2,
+}
+
+impl gpu_bytes::AsStd430 for DensityType {
+    fn as_std430(&self) -> gpu_bytes::Std430Bytes {
+        (*self as u32).as_std430()
+    }
+}
+
+/// Participating medium filling a volume, bounded by a sphere or box with a `MaterialType::Volume` material.
+#[derive(AsStd430, Clone, Copy, Debug, PartialEq)]
+pub struct Medium {
+    // Coefficients per unit of distance, at a density of 1
+    pub scattering: Vec3,
+    pub absorption: Vec3,
+
+    // Henyey-Greenstein asymmetry parameter g, positive values scatter forward
+    pub anisotropy: f32,
+
+    pub density_type: DensityType,
+    pub density_scale: f32,
+
+    // Only used by `DensityType::Noise`
+    pub noise_frequency: f32,
+
+    // Only used by `DensityType::Grid`, index into `DensityGrids::grids` plus 1
+    pub grid_index: u32,
+}
+
+impl Default for Medium {
+    fn default() -> Self {
+        Self {
+            scattering: Vec3::splat(1.0),
+            absorption: Vec3::splat(0.05),
+            anisotropy: 0.0,
+            density_type: DensityType::Homogeneous,
+            density_scale: 1.0,
+            noise_frequency: 1.0,
+            grid_index: 0,
+        }
+    }
+}
+
+impl Medium {
+    pub fn null() -> Self {
+        Self {
+            scattering: Vec3::ZERO,
+            absorption: Vec3::ZERO,
+            density_scale: 0.0,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::{fs, io, num::NonZeroU32, path::Path};
+
+use glam::UVec2;
+use image::ColorType;
+use wgpu::util::DeviceExt;
+
+use crate::{app::spectral_data, util};
+
+// Textures are bound as two arrays of views, so together they use twice this many of the binding array elements
+// requested in main.rs. The white placeholder counts as well.
+pub const MAX_IMAGE_TEXTURES: usize = 256;
+
+pub struct ImageTexture {
+    // File name without the extension
+    pub name: String,
+
+    // Decodes sRGB, for colors like the albedo
+    color_view: wgpu::TextureView,
+    // Reads the values as they are, for data like roughness and normals
+    data_view: wgpu::TextureView,
+}
+
+impl ImageTexture {
+    fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        name: String,
+        size: UVec2,
+        hdr: bool,
+        data: &[u8],
+    ) -> Self {
+        // HDR images are stored as linear floats, other images as 8 bit sRGB
+        let (format, color_format) = if hdr {
+            (
+                wgpu::TextureFormat::Rgba32Float,
+                wgpu::TextureFormat::Rgba32Float,
+            )
+        } else {
+            (
+                wgpu::TextureFormat::Rgba8Unorm,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+            )
+        };
+
+        // the color view of HDR images is the same as the data view
+        let view_formats = [color_format];
+        let view_formats = if hdr { &[] } else { &view_formats[..] };
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(&name),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats,
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            data,
+        );
+
+        let color_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(color_format),
+            ..Default::default()
+        });
+        let data_view = texture.create_view(&Default::default());
+
+        Self {
+            name,
+            color_view,
+            data_view,
+        }
+    }
+
+    // Loads a PNG, JPEG or Radiance HDR image
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> io::Result<Self> {
+        let image =
+            image::open(path).map_err(|err| spectral_data::invalid_data(path, err.to_string()))?;
+
+        let size = UVec2::new(image.width(), image.height());
+        let max_size = device.limits().max_texture_dimension_2d;
+
+        if size.max_element() > max_size {
+            return Err(spectral_data::invalid_data(
+                path,
+                format!("is larger than the maximum texture size of {}", max_size),
+            ));
+        }
+
+        let name = path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let hdr = matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+
+        let texture = if hdr {
+            let pixels = image.to_rgba32f();
+            Self::create(
+                device,
+                queue,
+                name,
+                size,
+                true,
+                bytemuck::cast_slice(pixels.as_raw()),
+            )
+        } else {
+            let pixels = image.to_rgba8();
+            Self::create(device, queue, name, size, false, pixels.as_raw())
+        };
+
+        Ok(texture)
+    }
+
+    // White, so sampling it doesn't change anything
+    fn placeholder(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::create(
+            device,
+            queue,
+            "placeholder_texture".to_owned(),
+            UVec2::ONE,
+            false,
+            &[255; 4],
+        )
+    }
+}
+
+/// Images loaded from assets/textures/materials for the texture slots of materials, bound as arrays indexed by the
+/// slots. The first texture is a white placeholder, like the null elements of the object buffers.
+pub struct ImageTextures {
+    pub textures: Vec<ImageTexture>,
+
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl ImageTextures {
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let mut textures = vec![ImageTexture::placeholder(device, queue)];
+
+        // materials just can't be textured without any images
+        if let Ok(entries) = fs::read_dir(util::asset_path("textures/materials")) {
+            let mut paths: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .and_then(|extension| extension.to_str())
+                        .is_some_and(|extension| {
+                            matches!(
+                                extension.to_ascii_lowercase().as_str(),
+                                "png" | "jpg" | "jpeg" | "hdr"
+                            )
+                        })
+                })
+                .collect();
+
+            // keep indices stable between runs
+            paths.sort();
+
+            if paths.len() >= MAX_IMAGE_TEXTURES {
+                log::warn!(
+                    "Only loading the first {} of {} material textures",
+                    MAX_IMAGE_TEXTURES - 1,
+                    paths.len()
+                );
+
+                paths.truncate(MAX_IMAGE_TEXTURES - 1);
+            }
+
+            for path in paths {
+                match ImageTexture::load(device, queue, &path) {
+                    Ok(texture) => textures.push(texture),
+                    Err(err) => log::error!("Failed to load material texture: {}", err),
+                }
+            }
+        }
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("image_texture_sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // wgputil only binds single resources, so the arrays are bound by hand
+        let count = NonZeroU32::new(textures.len() as u32);
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("image_textures"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+            ],
+        });
+
+        let color_views: Vec<_> = textures.iter().map(|texture| &texture.color_view).collect();
+        let data_views: Vec<_> = textures.iter().map(|texture| &texture.data_view).collect();
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("image_textures"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureViewArray(&color_views),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureViewArray(&data_views),
+                },
+            ],
+        });
+
+        Self {
+            textures,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn name(&self, index: u32) -> Option<&str> {
+        // the placeholder isn't a texture a material can pick
+        if index == 0 {
+            return None;
+        }
+
+        self.textures
+            .get(index as usize)
+            .map(|texture| texture.name.as_str())
+    }
+}
@@ -0,0 +1,334 @@
+use std::time::Duration;
+
+use bevy_ecs::{
+    change_detection::DetectChanges,
+    resource::Resource,
+    system::{Commands, Res, ResMut},
+};
+use gpu_bytes_derive::AsStd430;
+
+use crate::app::{
+    atmosphere::Atmosphere,
+    camera::binding::ScreenBinding,
+    fps::FpsCounter,
+    menu::{Menu, Settings},
+    object::Objects,
+    renderer::{profiler::RenderProfiler, RenderResolution, SurfaceState},
+    time::Time,
+};
+
+// Upper bound for the number of samples taken in a single frame when a frame time budget is set
+const MAX_SAMPLES_PER_FRAME: u32 = 64;
+
+// How often the sample count is read back from the GPU, in frames
+const READBACK_INTERVAL: u128 = 16;
+
+// Matches `SampleSettings` in pathtrace.slang, uploaded as push constants
+#[derive(AsStd430, Default)]
+pub struct SampleSettings {
+    samples_per_frame: u32,
+    sample_index_base: u32,
+    target_samples: u32,
+    epoch: u32,
+    history_epoch: u32,
+    reproject: u32,
+    max_history_length: u32,
+    indirect_clamp: f32,
+    spectral: u32,
+    wavelength_sampling: u32,
+    hero_wavelengths: u32,
+
+    // `PathtraceSettings` in pathtrace.slang, nested in the shader
+    max_depth: u32,
+    min_roulette_depth: u32,
+    roulette_strategy: u32,
+    survival_probability: f32,
+    max_diffuse_depth: u32,
+    max_specular_depth: u32,
+    max_transmission_depth: u32,
+    sky_mode: u32,
+    environment_rotation: f32,
+    environment_intensity: f32,
+}
+
+impl SampleSettings {
+    pub const SIZE: u32 = 84;
+}
+
+#[derive(Resource)]
+pub struct Accumulation {
+    // Whether the material pass should dispatch this frame
+    pub active: bool,
+    pub samples_per_frame: u32,
+    pub sample_settings: SampleSettings,
+
+    // Whether the material pass output this frame is accumulated onto its (possibly reprojected) history, or
+    // unchanged if it doesn't run, as opposed to being a fresh estimate
+    pub accumulated: bool,
+
+    // Incremented whenever something changes that makes the accumulated samples invalid. The shader only
+    // accumulates onto the history if it was written in the current epoch.
+    epoch: u32,
+    // Epoch the material color history was written in
+    history_epoch: u32,
+    previous_settings: Option<Settings>,
+
+    // Samples per pixel dispatched since accumulation was last reset. Tracked on the CPU, because the
+    // sample count read back from the GPU lags a few frames behind. Includes the samples the shader rejected, which
+    // only the total of a frame is known of, so the target sample count is a number of dispatched samples rather
+    // than accumulated ones.
+    dispatched_samples: u32,
+
+    // Index of the first sample of the next dispatch, never reset. The random seeds and jitter offsets are derived
+    // from it, so they don't repeat when the samples per frame change between frames.
+    sample_index_base: u32,
+
+    // Samples per pixel read back from the frame age channel of the material color texture
+    pub sample_count: u32,
+    pub readback_buffer: wgpu::Buffer,
+
+    // Samples rejected for being NaN or infinite in the frame that was last read back
+    pub rejected_samples: u32,
+
+    // Material pass time the current samples per frame were derived from
+    measured_pass_time: Option<Duration>,
+}
+
+impl Accumulation {
+    pub fn init(mut commands: Commands, surface_state: Res<SurfaceState>) {
+        let readback_buffer = surface_state
+            .gpu
+            .device
+            .create_buffer(&wgpu::BufferDescriptor {
+                label: Some("accumulation_readback_buffer"),
+                // one Rgba32Float texel, followed by the rejected sample count
+                size: 20,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        commands.insert_resource(Self {
+            active: true,
+            samples_per_frame: 1,
+            sample_settings: SampleSettings::default(),
+            accumulated: false,
+            // the history starts out empty, so it must not be accumulated onto
+            epoch: 1,
+            history_epoch: 0,
+            previous_settings: None,
+            dispatched_samples: 0,
+            sample_index_base: 0,
+            sample_count: 0,
+            readback_buffer,
+            rejected_samples: 0,
+            measured_pass_time: None,
+        });
+    }
+
+    // Upper bound for the samples per pixel kept when the history is reprojected. Stays below the target sample
+    // count, so moving the camera always renders new samples.
+    fn max_history_length(settings: &Settings) -> u32 {
+        if settings.target_samples > 0 {
+            settings.max_history_length.min(settings.target_samples - 1)
+        } else {
+            settings.max_history_length
+        }
+    }
+
+    /// Estimated time until the target sample count is reached, if there is one.
+    pub fn eta(&self, menu: &Menu, fps_counter: &FpsCounter) -> Option<Duration> {
+        let target_samples = menu.settings.target_samples;
+
+        if target_samples == 0 || !menu.settings.accumulate {
+            return None;
+        }
+
+        let samples_per_second = self.samples_per_frame as f64 * fps_counter.average_fps();
+        let remaining_samples = target_samples.saturating_sub(self.sample_count);
+
+        if remaining_samples == 0 {
+            return Some(Duration::ZERO);
+        }
+
+        if menu.settings.paused || samples_per_second <= 0.0 {
+            return None;
+        }
+
+        Some(Duration::from_secs_f64(
+            remaining_samples as f64 / samples_per_second,
+        ))
+    }
+
+    // Picks the number of samples per frame that fits into the frame time budget, based on the last
+    // measured material pass time
+    fn update_samples_per_frame(&mut self, menu: &Menu, profiler: &RenderProfiler) {
+        let budget = menu.settings.frame_time_budget;
+
+        if budget <= 0.0 {
+            self.samples_per_frame = 1;
+            self.measured_pass_time = None;
+            return;
+        }
+
+        let Some(pass_time) = profiler
+            .times
+            .iter()
+            .find(|(name, _)| &**name == "material_pass")
+            .map(|&(_, time)| time)
+        else {
+            return;
+        };
+
+        // The profiler only updates its times every so often, only adjust when there's a new measurement
+        if self.measured_pass_time == Some(pass_time) || pass_time.is_zero() {
+            return;
+        }
+
+        self.measured_pass_time = Some(pass_time);
+
+        let time_per_sample = pass_time.as_secs_f32() * 1000.0 / self.samples_per_frame as f32;
+        let samples_per_frame = (budget / time_per_sample).floor() as u32;
+
+        self.samples_per_frame = samples_per_frame.clamp(1, MAX_SAMPLES_PER_FRAME);
+    }
+
+    pub fn update(
+        mut accumulation: ResMut<Accumulation>,
+        menu: Res<Menu>,
+        screen_binding: Res<ScreenBinding>,
+        objects: Res<Objects>,
+        atmosphere: Res<Atmosphere>,
+        render_resolution: Res<RenderResolution>,
+        profiler: Res<RenderProfiler>,
+    ) {
+        let settings = &menu.settings;
+
+        accumulation.update_samples_per_frame(&menu, &profiler);
+
+        let settings_changed = accumulation
+            .previous_settings
+            .as_ref()
+            .is_none_or(|previous| settings.invalidates_accumulation(previous));
+
+        let camera_moved = !screen_binding.camera_uniform.is_still();
+
+        // The history can only be reprojected with last frame's camera, so moving while paused throws it away
+        let reset = !settings.accumulate
+            || settings_changed
+            || objects.is_changed()
+            || atmosphere.is_changed()
+            || render_resolution.is_changed()
+            || (camera_moved && (!settings.reproject || settings.paused));
+
+        let reproject = camera_moved && !reset;
+
+        accumulation.previous_settings = Some(settings.clone());
+
+        if reset {
+            accumulation.epoch = accumulation.epoch.wrapping_add(1);
+        }
+
+        if accumulation.epoch != accumulation.history_epoch {
+            accumulation.dispatched_samples = 0;
+        }
+
+        if reproject {
+            accumulation.dispatched_samples = accumulation
+                .dispatched_samples
+                .min(Self::max_history_length(settings));
+        }
+
+        let target_reached = settings.target_samples > 0
+            && accumulation.dispatched_samples >= settings.target_samples;
+
+        accumulation.active = !settings.paused && !target_reached;
+
+        // The last frame before the target only dispatches the samples that are left
+        let mut samples = accumulation.samples_per_frame;
+
+        if settings.target_samples > 0 {
+            samples = samples.min(
+                settings
+                    .target_samples
+                    .saturating_sub(accumulation.dispatched_samples),
+            );
+        }
+
+        if accumulation.active {
+            accumulation.dispatched_samples += samples;
+        }
+
+        accumulation.sample_settings = SampleSettings {
+            samples_per_frame: samples,
+            sample_index_base: accumulation.sample_index_base,
+            target_samples: settings.target_samples,
+            epoch: accumulation.epoch,
+            history_epoch: accumulation.history_epoch,
+            reproject: reproject as u32,
+            max_history_length: Self::max_history_length(settings),
+            indirect_clamp: if settings.clamp_indirect {
+                settings.indirect_clamp
+            } else {
+                0.0
+            },
+            spectral: settings.spectral as u32,
+            wavelength_sampling: settings.wavelength_sampling as u32,
+            hero_wavelengths: settings.hero_wavelengths as u32,
+            max_depth: settings.pathtrace.max_depth,
+            min_roulette_depth: settings.pathtrace.min_roulette_depth,
+            roulette_strategy: settings.pathtrace.roulette_strategy as u32,
+            survival_probability: settings.pathtrace.survival_probability,
+            max_diffuse_depth: settings.pathtrace.max_diffuse_depth,
+            max_specular_depth: settings.pathtrace.max_specular_depth,
+            max_transmission_depth: settings.pathtrace.max_transmission_depth,
+            sky_mode: settings.pathtrace.sky_mode as u32,
+            environment_rotation: settings.pathtrace.environment_rotation.to_radians(),
+            environment_intensity: settings.pathtrace.environment_intensity,
+        };
+
+        accumulation.accumulated =
+            !accumulation.active || accumulation.epoch == accumulation.history_epoch;
+
+        // The material pass writes this epoch's samples, which become the history for the next frame
+        if accumulation.active {
+            accumulation.history_epoch = accumulation.epoch;
+
+            // the shader indexes this frame's samples from the base, up to the samples dispatched
+            accumulation.sample_index_base = accumulation.sample_index_base.wrapping_add(samples);
+        }
+    }
+
+    pub fn post_render(
+        mut accumulation: ResMut<Accumulation>,
+        surface_state: Res<SurfaceState>,
+        time: Res<Time>,
+    ) {
+        // Reading back stalls until the GPU is done with the frame, so don't do it every frame
+        if time.frame_count() % READBACK_INTERVAL != 0 {
+            return;
+        }
+
+        let slice = accumulation.readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+
+        if let Err(err) = surface_state.gpu.device.poll(wgpu::PollType::Wait) {
+            log::warn!("Failed to read back the sample count: {}", err);
+            accumulation.readback_buffer.unmap();
+            return;
+        }
+
+        let (frame_age, rejected_samples) = {
+            let data = slice.get_mapped_range();
+
+            (
+                // alpha channel of the Rgba32Float texel
+                f32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+                u32::from_le_bytes([data[16], data[17], data[18], data[19]]),
+            )
+        };
+
+        accumulation.readback_buffer.unmap();
+        accumulation.sample_count = frame_age as u32;
+        accumulation.rejected_samples = rejected_samples;
+    }
+}
@@ -0,0 +1,137 @@
+use bevy_ecs::{
+    resource::Resource,
+    system::{Commands, Res},
+    world::World,
+};
+use glam::UVec3;
+use wgputil::shader::ShaderSource;
+
+use crate::{
+    app::{
+        atmosphere::{binding::AtmosphereBinding, Atmosphere},
+        menu::Menu,
+        renderer::{
+            graph::{NodeBuilder, NodeContext, RenderNode},
+            material::SkyMode,
+            SurfaceState,
+        },
+    },
+    util,
+};
+
+/*
+***Atmosphere lookup tables***
+
+Precomputed sky used by `SkyMode::Precomputed`, following Hillaire's "A Scalable and Production Ready Sky and
+Atmosphere Rendering Technique" (2020). Every table has a layer per wavelength, so spectral rendering keeps working:
+    - the transmittance table holds the transmittance to the top of the atmosphere
+    - the multiple scattering table holds the radiance of all scattering orders above the first, approximated from
+      the second order as a geometric series
+    - the sky-view table holds the radiance seen from the world origin in every direction, which the material pass
+      looks up for rays leaving the scene
+The tables live in the atmosphere bind group, and are only recomputed when the atmosphere changes.
+*/
+
+// Match atmosphere.slang
+pub const TRANSMITTANCE_LUT_SIZE: (u32, u32) = (256, 64);
+pub const MULTIPLE_SCATTERING_LUT_SIZE: (u32, u32) = (32, 32);
+pub const SKY_VIEW_LUT_SIZE: (u32, u32) = (192, 108);
+pub const LUT_WAVELENGTH_COUNT: u32 = 16;
+
+#[derive(Resource)]
+pub struct AtmosphereLutPipelines {
+    transmittance_pipeline: wgpu::ComputePipeline,
+    multiple_scattering_pipeline: wgpu::ComputePipeline,
+    sky_view_pipeline: wgpu::ComputePipeline,
+}
+
+impl AtmosphereLutPipelines {
+    pub fn init(
+        mut commands: Commands,
+        surface_state: Res<SurfaceState>,
+        atmosphere_binding: Res<AtmosphereBinding>,
+    ) {
+        let gpu = &surface_state.gpu;
+
+        let layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("atmosphere_lut_pipeline_layout"),
+                bind_group_layouts: &[&atmosphere_binding.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        // Every pass is an entry point of the same shader
+        let source = ShaderSource::load_spirv(util::shader_path("atmosphere_lut.slang"));
+        let module = wgputil::shader::create(&gpu.device, &source).unwrap();
+
+        let create_pipeline = |entry_point: &str| {
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&layout),
+                    module: &module,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+        };
+
+        commands.insert_resource(Self {
+            transmittance_pipeline: create_pipeline("computeTransmittance"),
+            multiple_scattering_pipeline: create_pipeline("computeMultipleScattering"),
+            sky_view_pipeline: create_pipeline("computeSkyView"),
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct AtmosphereLutNode {
+    // Atmosphere the tables were last computed for
+    computed_for: Option<Atmosphere>,
+}
+
+impl RenderNode for AtmosphereLutNode {
+    fn name(&self) -> &'static str {
+        "atmosphere_lut_pass"
+    }
+
+    // The tables aren't graph textures, since they don't depend on the render resolution
+    fn setup(&self, _builder: &mut NodeBuilder) {}
+
+    fn enabled(&self, world: &World) -> bool {
+        world.resource::<Menu>().settings.pathtrace.sky_mode == SkyMode::Precomputed
+            && self.computed_for.as_ref() != Some(world.resource::<Atmosphere>())
+    }
+
+    fn record(&mut self, world: &World, ctx: &mut NodeContext) {
+        let atmosphere = world.resource::<Atmosphere>();
+        let atmosphere_binding = world.resource::<AtmosphereBinding>();
+        let pipelines = world.resource::<AtmosphereLutPipelines>();
+
+        let mut compute_pass = ctx.begin_compute_pass("atmosphere_lut_pass");
+
+        compute_pass.set_bind_group(0, &atmosphere_binding.bind_group, &[]);
+
+        // Each pass reads the tables written by the ones before it
+        let passes = [
+            (&pipelines.transmittance_pipeline, TRANSMITTANCE_LUT_SIZE),
+            (
+                &pipelines.multiple_scattering_pipeline,
+                MULTIPLE_SCATTERING_LUT_SIZE,
+            ),
+            (&pipelines.sky_view_pipeline, SKY_VIEW_LUT_SIZE),
+        ];
+
+        for (pipeline, (width, height)) in passes {
+            let workgroup_sizes = UVec3::new(8, 8, 1);
+            let dimensions = UVec3::new(width, height, LUT_WAVELENGTH_COUNT);
+            let workgroups = (dimensions + workgroup_sizes - UVec3::ONE) / workgroup_sizes;
+
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+        }
+
+        self.computed_for = Some(atmosphere.clone());
+    }
+}
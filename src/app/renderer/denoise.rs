@@ -0,0 +1,458 @@
+use bevy_ecs::{
+    resource::Resource,
+    system::{Commands, Res},
+    world::{Mut, World},
+};
+use glam::UVec3;
+use gpu_bytes::AsStd430;
+use gpu_bytes_derive::AsStd430;
+use wgputil::shader::ShaderSource;
+
+use crate::{
+    app::{
+        camera::binding::ScreenBinding,
+        menu::Menu,
+        renderer::{
+            accumulation::Accumulation,
+            graph::{
+                GraphResources, GraphTextureDesc, NodeBuilder, NodeContext, RenderGraph, RenderNode,
+            },
+            material::{
+                MATERIAL_ALBEDO_TEXTURE, MATERIAL_COLOR_TEXTURE, MATERIAL_MOMENTS_TEXTURE,
+                MATERIAL_NORMAL_DEPTH_TEXTURE,
+            },
+            SurfaceState,
+        },
+    },
+    util,
+};
+
+/*
+***Denoiser***
+
+SVGF-style denoiser, filtering the demodulated illumination of the material pass:
+    - the temporal pass reprojects last frame's integrated illumination using the first hit of each pixel, and
+      estimates the per-pixel variance that drives the spatial filter. While the path tracer is accumulating (or
+      reprojecting its own history), its output is passed through instead, since it's already integrated over time.
+    - the à-trous passes run an edge-avoiding wavelet filter with increasing step sizes, guided by the normals,
+      hit distances and luminance variance. The last iteration multiplies the albedo back in.
+*/
+
+// rgb is the integrated illumination, a is the history length
+pub const DENOISE_ILLUMINATION_TEXTURE: &str = "denoise_illumination";
+pub const DENOISE_MOMENTS_TEXTURE: &str = "denoise_moments";
+
+// Intermediate à-trous results, rgb is the illumination and a its variance
+pub const DENOISE_PING_TEXTURE: &str = "denoise_ping";
+pub const DENOISE_PONG_TEXTURE: &str = "denoise_pong";
+
+pub const DENOISED_COLOR_TEXTURE: &str = "denoised_color";
+
+pub const MAX_ATROUS_ITERATIONS: u32 = 5;
+
+#[derive(Clone)]
+pub struct DenoiserSettings {
+    pub enabled: bool,
+    pub atrous_iterations: u32,
+
+    // Minimum weight of the current frame when blending with the history
+    pub temporal_alpha: f32,
+
+    pub color_phi: f32,
+    pub normal_phi: f32,
+    pub depth_phi: f32,
+}
+
+impl Default for DenoiserSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            atrous_iterations: 4,
+            temporal_alpha: 0.2,
+            color_phi: 4.0,
+            normal_phi: 128.0,
+            depth_phi: 1.0,
+        }
+    }
+}
+
+// Matches `DenoiseParameters` in denoise.slang, uploaded as push constants
+#[derive(AsStd430)]
+pub struct DenoiseParameters {
+    temporal_alpha: f32,
+    color_phi: f32,
+    normal_phi: f32,
+    depth_phi: f32,
+    step_size: u32,
+    last_iteration: u32,
+    accumulated: u32,
+}
+
+impl DenoiseParameters {
+    pub const SIZE: u32 = 28;
+}
+
+fn sampled_entry(view: &wgpu::TextureView) -> wgputil::binding::BindingEntry<'_> {
+    wgputil::binding::bind_texture(
+        view,
+        wgpu::TextureSampleType::Float { filterable: false },
+        wgpu::TextureViewDimension::D2,
+    )
+}
+
+fn storage_entry<'a>(
+    view: &'a wgpu::TextureView,
+    resources: &GraphResources,
+    name: &str,
+) -> wgputil::binding::BindingEntry<'a> {
+    wgputil::binding::bind_storage_texture(
+        view,
+        resources.texture_at(name, 0).format(),
+        wgpu::TextureViewDimension::D2,
+        wgpu::StorageTextureAccess::WriteOnly,
+    )
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    label: &str,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    views: &[wgpu::TextureView],
+) -> wgpu::BindGroup {
+    let binding_resources: Vec<_> = views
+        .iter()
+        .map(wgpu::BindingResource::TextureView)
+        .collect();
+
+    wgputil::binding::create_sequential_with_layout(
+        device,
+        label,
+        bind_group_layout,
+        &binding_resources,
+    )
+}
+
+#[derive(Resource)]
+pub struct DenoiseBinding {
+    // Outputs of the material pass, indexed by the current index of the material color texture
+    pub input_bind_group_layout: wgpu::BindGroupLayout,
+    pub input_bind_groups: [wgpu::BindGroup; 2],
+
+    // Temporal history, indexed by the current index of the denoise illumination texture
+    pub temporal_bind_group_layout: wgpu::BindGroupLayout,
+    pub temporal_bind_groups: [wgpu::BindGroup; 2],
+
+    // One bind group for each direction of the à-trous ping-pong, see `DenoiseBinding::atrous_bind_group`
+    pub atrous_bind_group_layout: wgpu::BindGroupLayout,
+    pub atrous_bind_groups: [wgpu::BindGroup; 4],
+}
+
+impl DenoiseBinding {
+    pub fn init(
+        mut commands: Commands,
+        surface_state: Res<SurfaceState>,
+        render_graph: Res<RenderGraph>,
+    ) {
+        let device = &surface_state.gpu.device;
+        let resources = render_graph.resources();
+
+        let input_views = Self::input_views(resources, 0);
+        let (input_bind_group_layout, input_bind_group) =
+            wgputil::binding::create_sequential_linked(
+                device,
+                "denoise_input_binding",
+                &input_views.iter().map(sampled_entry).collect::<Vec<_>>(),
+            );
+
+        let temporal_views = Self::temporal_views(resources, 0);
+        let (temporal_bind_group_layout, temporal_bind_group) =
+            wgputil::binding::create_sequential_linked(
+                device,
+                "denoise_temporal_binding",
+                &[
+                    storage_entry(&temporal_views[0], resources, DENOISE_ILLUMINATION_TEXTURE),
+                    storage_entry(&temporal_views[1], resources, DENOISE_MOMENTS_TEXTURE),
+                    sampled_entry(&temporal_views[2]),
+                    sampled_entry(&temporal_views[3]),
+                    storage_entry(&temporal_views[4], resources, DENOISE_PING_TEXTURE),
+                ],
+            );
+
+        let atrous_views = Self::atrous_views(resources, 0);
+        let (atrous_bind_group_layout, atrous_bind_group) =
+            wgputil::binding::create_sequential_linked(
+                device,
+                "denoise_atrous_binding",
+                &[
+                    sampled_entry(&atrous_views[0]),
+                    storage_entry(&atrous_views[1], resources, DENOISE_PONG_TEXTURE),
+                ],
+            );
+
+        let mut denoise_binding = Self {
+            input_bind_groups: [input_bind_group.clone(), input_bind_group],
+            input_bind_group_layout,
+            temporal_bind_groups: [temporal_bind_group.clone(), temporal_bind_group],
+            temporal_bind_group_layout,
+            atrous_bind_groups: [0; 4].map(|_| atrous_bind_group.clone()),
+            atrous_bind_group_layout,
+        };
+
+        // The linked bind groups only cover one variant each, create all of them
+        denoise_binding.rebuild(device, resources);
+
+        commands.insert_resource(denoise_binding);
+    }
+
+    fn input_views(resources: &GraphResources, current_index: usize) -> [wgpu::TextureView; 5] {
+        [
+            resources.view_at(MATERIAL_COLOR_TEXTURE, current_index),
+            resources.view_at(MATERIAL_MOMENTS_TEXTURE, current_index),
+            resources.view_at(MATERIAL_ALBEDO_TEXTURE, 0),
+            resources.view_at(MATERIAL_NORMAL_DEPTH_TEXTURE, current_index),
+            resources.view_at(MATERIAL_NORMAL_DEPTH_TEXTURE, 1 - current_index),
+        ]
+    }
+
+    fn temporal_views(resources: &GraphResources, current_index: usize) -> [wgpu::TextureView; 5] {
+        [
+            resources.view_at(DENOISE_ILLUMINATION_TEXTURE, current_index),
+            resources.view_at(DENOISE_MOMENTS_TEXTURE, current_index),
+            resources.view_at(DENOISE_ILLUMINATION_TEXTURE, 1 - current_index),
+            resources.view_at(DENOISE_MOMENTS_TEXTURE, 1 - current_index),
+            resources.view_at(DENOISE_PING_TEXTURE, 0),
+        ]
+    }
+
+    // Ping to pong, pong to ping, then the same for the last iteration writing to the denoised color
+    fn atrous_views(resources: &GraphResources, direction: usize) -> [wgpu::TextureView; 2] {
+        let (input, output) = match direction {
+            0 => (DENOISE_PING_TEXTURE, DENOISE_PONG_TEXTURE),
+            1 => (DENOISE_PONG_TEXTURE, DENOISE_PING_TEXTURE),
+            2 => (DENOISE_PING_TEXTURE, DENOISED_COLOR_TEXTURE),
+            _ => (DENOISE_PONG_TEXTURE, DENOISED_COLOR_TEXTURE),
+        };
+
+        [resources.view_at(input, 0), resources.view_at(output, 0)]
+    }
+
+    fn rebuild(&mut self, device: &wgpu::Device, resources: &GraphResources) {
+        self.input_bind_groups = [0, 1].map(|current_index| {
+            create_bind_group(
+                device,
+                "denoise_input_binding",
+                &self.input_bind_group_layout,
+                &Self::input_views(resources, current_index),
+            )
+        });
+
+        self.temporal_bind_groups = [0, 1].map(|current_index| {
+            create_bind_group(
+                device,
+                "denoise_temporal_binding",
+                &self.temporal_bind_group_layout,
+                &Self::temporal_views(resources, current_index),
+            )
+        });
+
+        self.atrous_bind_groups = [0, 1, 2, 3].map(|direction| {
+            create_bind_group(
+                device,
+                "denoise_atrous_binding",
+                &self.atrous_bind_group_layout,
+                &Self::atrous_views(resources, direction),
+            )
+        });
+    }
+
+    pub fn input_bind_group(&self, resources: &GraphResources) -> &wgpu::BindGroup {
+        &self.input_bind_groups[resources.current_index(MATERIAL_COLOR_TEXTURE)]
+    }
+
+    pub fn temporal_bind_group(&self, resources: &GraphResources) -> &wgpu::BindGroup {
+        &self.temporal_bind_groups[resources.current_index(DENOISE_ILLUMINATION_TEXTURE)]
+    }
+
+    /// The temporal pass writes to the ping texture, so even iterations read from ping and odd ones from pong.
+    pub fn atrous_bind_group(&self, iteration: u32, last_iteration: bool) -> &wgpu::BindGroup {
+        let direction = (iteration % 2) as usize;
+
+        if last_iteration {
+            &self.atrous_bind_groups[2 + direction]
+        } else {
+            &self.atrous_bind_groups[direction]
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct DenoisePipelines {
+    temporal_pipeline: wgpu::ComputePipeline,
+    atrous_pipeline: wgpu::ComputePipeline,
+}
+
+impl DenoisePipelines {
+    pub fn init(
+        mut commands: Commands,
+        surface_state: Res<SurfaceState>,
+        screen_binding: Res<ScreenBinding>,
+        denoise_binding: Res<DenoiseBinding>,
+    ) {
+        let gpu = &surface_state.gpu;
+
+        let create_pipeline = |path: &str, bind_group_layout: &wgpu::BindGroupLayout| {
+            let layout = gpu
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some(&format!("{}_layout", path)),
+                    bind_group_layouts: &[
+                        &screen_binding.bind_group_layout,
+                        &denoise_binding.input_bind_group_layout,
+                        bind_group_layout,
+                    ],
+                    push_constant_ranges: &[wgpu::PushConstantRange {
+                        stages: wgpu::ShaderStages::COMPUTE,
+                        range: 0..DenoiseParameters::SIZE,
+                    }],
+                });
+
+            let source = ShaderSource::load_spirv(util::shader_path(path));
+            let module = wgputil::shader::create(&gpu.device, &source).unwrap();
+
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(path),
+                    layout: Some(&layout),
+                    module: &module,
+                    entry_point: Some("compute"),
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+        };
+
+        commands.insert_resource(Self {
+            temporal_pipeline: create_pipeline(
+                "denoise_temporal.slang",
+                &denoise_binding.temporal_bind_group_layout,
+            ),
+            atrous_pipeline: create_pipeline(
+                "denoise_atrous.slang",
+                &denoise_binding.atrous_bind_group_layout,
+            ),
+        });
+    }
+}
+
+pub struct DenoiseNode;
+
+impl RenderNode for DenoiseNode {
+    fn name(&self) -> &'static str {
+        "denoise_pass"
+    }
+
+    fn setup(&self, builder: &mut NodeBuilder) {
+        builder.read_texture(MATERIAL_COLOR_TEXTURE);
+        builder.read_texture(MATERIAL_MOMENTS_TEXTURE);
+        builder.read_texture(MATERIAL_ALBEDO_TEXTURE);
+        builder.read_texture(MATERIAL_NORMAL_DEPTH_TEXTURE);
+
+        let storage = |format, history| GraphTextureDesc {
+            format,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            history,
+        };
+
+        builder.create_texture(
+            DENOISE_ILLUMINATION_TEXTURE,
+            storage(wgpu::TextureFormat::Rgba32Float, true),
+        );
+        builder.create_texture(
+            DENOISE_MOMENTS_TEXTURE,
+            storage(wgpu::TextureFormat::Rg32Float, true),
+        );
+        builder.create_texture(
+            DENOISE_PING_TEXTURE,
+            storage(wgpu::TextureFormat::Rgba32Float, false),
+        );
+        builder.create_texture(
+            DENOISE_PONG_TEXTURE,
+            storage(wgpu::TextureFormat::Rgba32Float, false),
+        );
+        // Copyable so it can be exported
+        builder.create_texture(
+            DENOISED_COLOR_TEXTURE,
+            GraphTextureDesc {
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                history: false,
+            },
+        );
+    }
+
+    fn prepare(&mut self, world: &mut World, resources: &GraphResources) {
+        world.resource_scope(|world, mut denoise_binding: Mut<DenoiseBinding>| {
+            let surface_state = world.resource::<SurfaceState>();
+            denoise_binding.rebuild(&surface_state.gpu.device, resources);
+        });
+    }
+
+    fn enabled(&self, world: &World) -> bool {
+        world.resource::<Menu>().settings.denoiser.enabled
+    }
+
+    fn record(&mut self, world: &World, ctx: &mut NodeContext) {
+        let settings = &world.resource::<Menu>().settings.denoiser;
+        let accumulation = world.resource::<Accumulation>();
+        let screen_binding = world.resource::<ScreenBinding>();
+        let denoise_binding = world.resource::<DenoiseBinding>();
+        let denoise_pipelines = world.resource::<DenoisePipelines>();
+
+        let resources = ctx.resources;
+        let size = resources.size();
+
+        let workgroup_sizes = UVec3::new(8, 8, 1);
+        let dimensions = UVec3::new(size.width, size.height, 1);
+        let workgroups = (dimensions + workgroup_sizes - UVec3::ONE) / workgroup_sizes;
+
+        let mut parameters = DenoiseParameters {
+            temporal_alpha: settings.temporal_alpha,
+            color_phi: settings.color_phi,
+            normal_phi: settings.normal_phi,
+            depth_phi: settings.depth_phi,
+            step_size: 1,
+            last_iteration: 0,
+            accumulated: accumulation.accumulated as u32,
+        };
+
+        let mut compute_pass = ctx.begin_compute_pass("denoise_pass");
+
+        compute_pass.set_bind_group(0, &screen_binding.bind_group, &[]);
+        compute_pass.set_bind_group(1, denoise_binding.input_bind_group(resources), &[]);
+
+        compute_pass.set_pipeline(&denoise_pipelines.temporal_pipeline);
+        compute_pass.set_bind_group(2, denoise_binding.temporal_bind_group(resources), &[]);
+        compute_pass.set_push_constants(0, parameters.as_std430().as_slice());
+        compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+
+        compute_pass.set_pipeline(&denoise_pipelines.atrous_pipeline);
+
+        let iterations = settings.atrous_iterations.clamp(1, MAX_ATROUS_ITERATIONS);
+
+        for iteration in 0..iterations {
+            let last_iteration = iteration == iterations - 1;
+
+            parameters.step_size = 1 << iteration;
+            parameters.last_iteration = last_iteration as u32;
+
+            compute_pass.set_bind_group(
+                2,
+                denoise_binding.atrous_bind_group(iteration, last_iteration),
+                &[],
+            );
+            compute_pass.set_push_constants(0, parameters.as_std430().as_slice());
+            compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+        }
+    }
+}
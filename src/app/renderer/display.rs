@@ -2,9 +2,11 @@ use std::{collections::HashMap, sync::Arc};
 
 use bevy_ecs::{
     resource::Resource,
-    system::{Commands, Local, Res, ResMut},
+    system::{Commands, Res},
+    world::{Mut, World},
 };
 use gpu_bytes::AsStd430;
+use gpu_bytes_derive::AsStd430;
 use wgpu::util::DeviceExt;
 use wgputil::shader::ShaderSource;
 
@@ -12,21 +14,102 @@ use crate::{
     app::{
         camera::binding::ScreenBinding,
         lookup::CameraResponseBinding,
+        menu::Menu,
         renderer::{
-            material::MaterialTextures, profiler::RenderProfiler, FrameRecord, RendererViewport,
-            SurfaceState,
+            denoise::DENOISED_COLOR_TEXTURE,
+            graph::{GraphResources, NodeBuilder, NodeContext, RenderGraph, RenderNode},
+            material::{
+                PathtraceDebugMode, MATERIAL_ALBEDO_TEXTURE, MATERIAL_COLOR_TEXTURE,
+                MATERIAL_IDS_TEXTURE, MATERIAL_MOMENTS_TEXTURE, MATERIAL_NORMAL_DEPTH_TEXTURE,
+                MATERIAL_POSITION_TEXTURE,
+            },
+            RendererViewport, SurfaceState,
         },
     },
     util,
 };
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayFilter {
+    Nearest,
+    Bilinear,
+    Lanczos,
+}
+
+// Textures bound for the debug views, in binding order. Matches `Aovs` in final.slang.
+const AOV_TEXTURES: [&str; 5] = [
+    MATERIAL_ALBEDO_TEXTURE,
+    MATERIAL_NORMAL_DEPTH_TEXTURE,
+    MATERIAL_POSITION_TEXTURE,
+    MATERIAL_IDS_TEXTURE,
+    MATERIAL_MOMENTS_TEXTURE,
+];
+
+// Shows one of the arbitrary output variables of the material pass instead of the beauty render
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    None,
+    Albedo,
+    Normal,
+    Position,
+    Depth,
+    MaterialIndex,
+    PrimitiveId,
+    BounceCount,
+    Variance,
+    SampleCount,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 10] = [
+        DebugView::None,
+        DebugView::Albedo,
+        DebugView::Normal,
+        DebugView::Position,
+        DebugView::Depth,
+        DebugView::MaterialIndex,
+        DebugView::PrimitiveId,
+        DebugView::BounceCount,
+        DebugView::Variance,
+        DebugView::SampleCount,
+    ];
+}
+
+// Which image the display pass shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayView {
+    Raw,
+    Denoised,
+    // Raw on the left side of the split position, denoised on the right
+    Split,
+}
+
+// Matches `DisplaySettings` in final.slang, uploaded as push constants
+#[derive(AsStd430)]
+pub struct DisplaySettings {
+    filter: u32,
+    view: u32,
+    split_position: f32,
+    debug_view: u32,
+    pathtrace_debug: u32,
+}
+
+impl DisplaySettings {
+    pub const SIZE: u32 = 20;
+}
+
 #[derive(Resource)]
 pub struct DisplayBinding {
     sampler: wgpu::Sampler,
     viewport_buffer: wgpu::Buffer,
 
-    pub bind_group: wgpu::BindGroup,
+    // One bind group for each texture of the material color ping-pong pair, indexed by its current index
+    pub bind_groups: [wgpu::BindGroup; 2],
     pub bind_group_layout: wgpu::BindGroupLayout,
+
+    // Arbitrary output variables of the material pass for the debug views, indexed the same way
+    pub aov_bind_groups: [wgpu::BindGroup; 2],
+    pub aov_bind_group_layout: wgpu::BindGroupLayout,
 }
 
 impl DisplayBinding {
@@ -34,16 +117,19 @@ impl DisplayBinding {
         mut commands: Commands,
         surface_state: Res<SurfaceState>,
         renderer_viewport: Res<RendererViewport>,
-        material_textures: Res<MaterialTextures>,
+        render_graph: Res<RenderGraph>,
     ) {
-        let view = material_textures
-            .current_texture
-            .create_view(&Default::default());
-        let sample_type = wgputil::texture::sample_type(
-            &surface_state.gpu.device,
-            &material_textures.current_texture,
-        )
-        .unwrap();
+        let resources = render_graph.resources();
+        let input_texture = resources.texture_at(MATERIAL_COLOR_TEXTURE, 0);
+
+        let view = input_texture.create_view(&Default::default());
+        let sample_type =
+            wgputil::texture::sample_type(&surface_state.gpu.device, input_texture).unwrap();
+
+        let denoised_texture = resources.texture_at(DENOISED_COLOR_TEXTURE, 0);
+        let denoised_view = denoised_texture.create_view(&Default::default());
+        let denoised_sample_type =
+            wgputil::texture::sample_type(&surface_state.gpu.device, denoised_texture).unwrap();
 
         let sampler = surface_state
             .gpu
@@ -70,43 +156,162 @@ impl DisplayBinding {
                 wgputil::binding::bind_texture(&view, sample_type, wgpu::TextureViewDimension::D2),
                 wgputil::binding::bind_sampler(&sampler, wgpu::SamplerBindingType::NonFiltering),
                 wgputil::binding::bind_buffer_uniform(&viewport_buffer),
+                wgputil::binding::bind_texture(
+                    &denoised_view,
+                    denoised_sample_type,
+                    wgpu::TextureViewDimension::D2,
+                ),
             ],
         );
 
+        let swapped_bind_group = Self::create_bind_group(
+            &surface_state.gpu.device,
+            &bind_group_layout,
+            &sampler,
+            &viewport_buffer,
+            resources,
+            1,
+        );
+
+        let aov_views = Self::aov_views(resources, 0);
+        let aov_entries: Vec<_> = AOV_TEXTURES
+            .iter()
+            .zip(&aov_views)
+            .map(|(&name, view)| {
+                let sample_type = wgputil::texture::sample_type(
+                    &surface_state.gpu.device,
+                    resources.texture_at(name, 0),
+                )
+                .unwrap();
+
+                wgputil::binding::bind_texture(view, sample_type, wgpu::TextureViewDimension::D2)
+            })
+            .collect();
+
+        let (aov_bind_group_layout, aov_bind_group) = wgputil::binding::create_sequential_linked(
+            &surface_state.gpu.device,
+            "display_aov_binding",
+            &aov_entries,
+        );
+
+        let swapped_aov_bind_group = Self::create_aov_bind_group(
+            &surface_state.gpu.device,
+            &aov_bind_group_layout,
+            resources,
+            1,
+        );
+
         commands.insert_resource(Self {
             sampler,
             viewport_buffer,
-            bind_group,
+            bind_groups: [bind_group, swapped_bind_group],
             bind_group_layout,
+            aov_bind_groups: [aov_bind_group, swapped_aov_bind_group],
+            aov_bind_group_layout,
         });
     }
 
+    fn aov_views(resources: &GraphResources, current_index: usize) -> Vec<wgpu::TextureView> {
+        AOV_TEXTURES
+            .iter()
+            .map(|&name| {
+                // Only some of the textures have history
+                let index = if resources.has_history(name) {
+                    current_index
+                } else {
+                    0
+                };
+
+                resources.view_at(name, index)
+            })
+            .collect()
+    }
+
+    fn create_aov_bind_group(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        resources: &GraphResources,
+        current_index: usize,
+    ) -> wgpu::BindGroup {
+        let views = Self::aov_views(resources, current_index);
+        let binding_resources: Vec<_> = views
+            .iter()
+            .map(wgpu::BindingResource::TextureView)
+            .collect();
+
+        wgputil::binding::create_sequential_with_layout(
+            device,
+            "display_aov_binding",
+            bind_group_layout,
+            &binding_resources,
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        viewport_buffer: &wgpu::Buffer,
+        resources: &GraphResources,
+        current_index: usize,
+    ) -> wgpu::BindGroup {
+        let view = resources.view_at(MATERIAL_COLOR_TEXTURE, current_index);
+        let denoised_view = resources.view_at(DENOISED_COLOR_TEXTURE, 0);
+
+        wgputil::binding::create_sequential_with_layout(
+            device,
+            "display_input_binding",
+            bind_group_layout,
+            &[
+                wgpu::BindingResource::TextureView(&view),
+                wgpu::BindingResource::Sampler(sampler),
+                viewport_buffer.as_entire_binding(),
+                wgpu::BindingResource::TextureView(&denoised_view),
+            ],
+        )
+    }
+
     pub fn on_resize(
-        mut display_binding: ResMut<DisplayBinding>,
+        display_binding: Res<DisplayBinding>,
         surface_state: Res<SurfaceState>,
         renderer_viewport: Res<RendererViewport>,
-        material_textures: Res<MaterialTextures>,
     ) {
         surface_state.gpu.queue.write_buffer(
             &display_binding.viewport_buffer,
             0,
             renderer_viewport.as_std430().as_slice(),
         );
+    }
 
-        let view = material_textures
-            .current_texture
-            .create_view(&Default::default());
+    fn rebuild(&mut self, device: &wgpu::Device, resources: &GraphResources) {
+        self.bind_groups = [0, 1].map(|current_index| {
+            Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.sampler,
+                &self.viewport_buffer,
+                resources,
+                current_index,
+            )
+        });
 
-        display_binding.bind_group = wgputil::binding::create_sequential_with_layout(
-            &surface_state.gpu.device,
-            "display_input_binding",
-            &display_binding.bind_group_layout,
-            &[
-                wgpu::BindingResource::TextureView(&view),
-                wgpu::BindingResource::Sampler(&display_binding.sampler),
-                display_binding.viewport_buffer.as_entire_binding(),
-            ],
-        );
+        self.aov_bind_groups = [0, 1].map(|current_index| {
+            Self::create_aov_bind_group(
+                device,
+                &self.aov_bind_group_layout,
+                resources,
+                current_index,
+            )
+        });
+    }
+
+    /// The bind group reading the material color texture written most recently.
+    pub fn bind_group(&self, resources: &GraphResources) -> &wgpu::BindGroup {
+        &self.bind_groups[resources.current_index(MATERIAL_COLOR_TEXTURE)]
+    }
+
+    pub fn aov_bind_group(&self, resources: &GraphResources) -> &wgpu::BindGroup {
+        &self.aov_bind_groups[resources.current_index(MATERIAL_COLOR_TEXTURE)]
     }
 }
 
@@ -134,8 +339,12 @@ impl DisplayPipelines {
                     &screen_binding.bind_group_layout,
                     &camera_response_binding.bind_group_layout,
                     &display_binding.bind_group_layout,
+                    &display_binding.aov_bind_group_layout,
                 ],
-                push_constant_ranges: &[],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::FRAGMENT,
+                    range: 0..DisplaySettings::SIZE,
+                }],
             });
 
         let mut pipelines = HashMap::new();
@@ -200,33 +409,57 @@ impl DisplayPipelines {
     }
 }
 
-#[expect(clippy::too_many_arguments)]
-pub fn draw(
-    mut time_query_index: Local<Option<usize>>,
-
-    surface_state: Res<SurfaceState>,
-    mut profiler: ResMut<RenderProfiler>,
-    mut frame: ResMut<FrameRecord>,
-
-    screen_binding: Res<ScreenBinding>,
-    camera_response_binding: Res<CameraResponseBinding>,
-    display_binding: Res<DisplayBinding>,
+pub struct DisplayNode;
 
-    display_pipelines: Res<DisplayPipelines>,
-) {
-    if time_query_index.is_none() {
-        *time_query_index = Some(profiler.push(&surface_state.gpu, "display_pass"));
+impl RenderNode for DisplayNode {
+    fn name(&self) -> &'static str {
+        "display_pass"
     }
 
-    let (_, time_query) = &mut profiler.time_queries[time_query_index.unwrap()];
+    fn setup(&self, builder: &mut NodeBuilder) {
+        builder.read_texture(MATERIAL_COLOR_TEXTURE);
+        builder.read_texture(DENOISED_COLOR_TEXTURE);
+    }
 
-    let view = frame.surface_texture_view.clone();
+    fn prepare(&mut self, world: &mut World, resources: &GraphResources) {
+        world.resource_scope(|world, mut display_binding: Mut<DisplayBinding>| {
+            let surface_state = world.resource::<SurfaceState>();
+            display_binding.rebuild(&surface_state.gpu.device, resources);
+        });
+    }
 
-    let mut render_pass = frame
-        .encoder
-        .begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("display_pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+    fn record(&mut self, world: &World, ctx: &mut NodeContext) {
+        let screen_binding = world.resource::<ScreenBinding>();
+        let camera_response_binding = world.resource::<CameraResponseBinding>();
+        let display_binding = world.resource::<DisplayBinding>();
+        let display_pipelines = world.resource::<DisplayPipelines>();
+        let menu = world.resource::<Menu>();
+
+        let pathtrace_debug = menu.settings.pathtrace_debug != PathtraceDebugMode::None;
+
+        // The denoised texture isn't written to while the denoiser is off, and the debug modes aren't meant to be
+        // denoised
+        let view = if menu.settings.denoiser.enabled && !pathtrace_debug {
+            menu.settings.display_view
+        } else {
+            DisplayView::Raw
+        };
+
+        let display_settings = DisplaySettings {
+            filter: menu.settings.display_filter as u32,
+            view: view as u32,
+            split_position: menu.settings.split_position,
+            debug_view: menu.settings.debug_view as u32,
+            pathtrace_debug: pathtrace_debug as u32,
+        };
+
+        let view = ctx.surface_texture_view.clone();
+        let display_bind_group = display_binding.bind_group(ctx.resources);
+        let aov_bind_group = display_binding.aov_bind_group(ctx.resources);
+
+        let mut render_pass = ctx.begin_render_pass(
+            "display_pass",
+            &[Some(wgpu::RenderPassColorAttachment {
                 view: &view,
                 resolve_target: None,
                 ops: wgpu::Operations {
@@ -234,16 +467,20 @@ pub fn draw(
                     store: wgpu::StoreOp::Store,
                 },
             })],
-            depth_stencil_attachment: None,
-            timestamp_writes: Some(time_query.render_timestamp_writes()),
-            occlusion_query_set: None,
-        });
+        );
 
-    render_pass.set_bind_group(0, &screen_binding.bind_group, &[]);
-    render_pass.set_bind_group(1, &camera_response_binding.bind_group, &[]);
-    render_pass.set_bind_group(2, &display_binding.bind_group, &[]);
+        render_pass.set_bind_group(0, &screen_binding.bind_group, &[]);
+        render_pass.set_bind_group(1, &camera_response_binding.bind_group, &[]);
+        render_pass.set_bind_group(2, display_bind_group, &[]);
+        render_pass.set_bind_group(3, aov_bind_group, &[]);
 
-    render_pass.set_pipeline(display_pipelines.get_active_pipeline());
+        render_pass.set_pipeline(display_pipelines.get_active_pipeline());
+        render_pass.set_push_constants(
+            wgpu::ShaderStages::FRAGMENT,
+            0,
+            display_settings.as_std430().as_slice(),
+        );
 
-    render_pass.draw(0..6, 0..1);
+        render_pass.draw(0..6, 0..1);
+    }
 }
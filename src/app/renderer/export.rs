@@ -0,0 +1,307 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy_ecs::{
+    event::{EventCursor, Events},
+    resource::Resource,
+    system::{Res, ResMut},
+    world::{Mut, World},
+};
+
+use crate::app::{
+    events::ExportEvent,
+    menu::Menu,
+    renderer::{
+        denoise::DENOISED_COLOR_TEXTURE,
+        graph::RenderGraph,
+        material::{
+            MATERIAL_ALBEDO_TEXTURE, MATERIAL_COLOR_TEXTURE, MATERIAL_IDS_TEXTURE,
+            MATERIAL_MOMENTS_TEXTURE, MATERIAL_NORMAL_DEPTH_TEXTURE, MATERIAL_POSITION_TEXTURE,
+        },
+        FrameRecord, SurfaceState,
+    },
+};
+
+// Directory exports are written to, relative to the working directory
+const EXPORT_DIRECTORY: &str = "renders";
+
+// How a single output file is derived from the texels of a graph texture
+#[derive(Clone, Copy)]
+enum Channels {
+    // First three float channels, written as a color PFM
+    Rgb,
+    // A single float channel, written as a grayscale PFM
+    Float(usize),
+    // A single uint channel, converted to float and written as a grayscale PFM
+    Uint(usize),
+    // Variance from the first two moments of luminance
+    Variance,
+}
+
+// Files written for every export, and the graph texture each one is read from
+const OUTPUTS: [(&str, &str, Channels); 10] = [
+    ("beauty", MATERIAL_COLOR_TEXTURE, Channels::Rgb),
+    ("albedo", MATERIAL_ALBEDO_TEXTURE, Channels::Rgb),
+    ("normal", MATERIAL_NORMAL_DEPTH_TEXTURE, Channels::Rgb),
+    ("depth", MATERIAL_NORMAL_DEPTH_TEXTURE, Channels::Float(3)),
+    ("position", MATERIAL_POSITION_TEXTURE, Channels::Rgb),
+    ("material_index", MATERIAL_IDS_TEXTURE, Channels::Uint(0)),
+    ("primitive_id", MATERIAL_IDS_TEXTURE, Channels::Uint(1)),
+    ("bounce_count", MATERIAL_IDS_TEXTURE, Channels::Uint(2)),
+    ("variance", MATERIAL_MOMENTS_TEXTURE, Channels::Variance),
+    ("sample_count", MATERIAL_COLOR_TEXTURE, Channels::Float(3)),
+];
+
+// Copy of a graph texture waiting to be read back
+struct PendingCopy {
+    texture_name: &'static str,
+    buffer: wgpu::Buffer,
+    bytes_per_texel: u32,
+    padded_bytes_per_row: u32,
+}
+
+/// Writes the render and its arbitrary output variables to PFM files when an `ExportEvent` is sent.
+#[derive(Resource, Default)]
+pub struct Export {
+    // Copies recorded into this frame's encoder, read back once the frame has been submitted
+    pending: Vec<PendingCopy>,
+    size: wgpu::Extent3d,
+
+    events: EventCursor<ExportEvent>,
+}
+
+impl Export {
+    pub fn init(world: &mut World) {
+        world.insert_resource(Self::default());
+    }
+
+    // Records copies of every exported texture into buffers, if an export was requested this frame
+    pub fn record(world: &mut World) {
+        world.resource_scope(|world, mut export: Mut<Export>| {
+            let events = world.resource::<Events<ExportEvent>>();
+
+            if export.events.read(events).count() == 0 {
+                return;
+            }
+
+            world.resource_scope(|world, mut frame: Mut<FrameRecord>| {
+                let device = &world.resource::<SurfaceState>().gpu.device;
+                let resources = world.resource::<RenderGraph>().resources();
+                let denoised = world.resource::<Menu>().settings.denoiser.enabled;
+
+                let size = resources.size();
+
+                let mut texture_names: Vec<&'static str> = Vec::new();
+
+                for (_, texture_name, _) in OUTPUTS {
+                    if !texture_names.contains(&texture_name) {
+                        texture_names.push(texture_name);
+                    }
+                }
+
+                // The denoised texture only holds this frame's render if the denoiser ran
+                if denoised {
+                    texture_names.push(DENOISED_COLOR_TEXTURE);
+                }
+
+                export.size = size;
+                export.pending = texture_names
+                    .into_iter()
+                    .map(|texture_name| {
+                        let texture = resources.texture(texture_name);
+
+                        let bytes_per_texel = texture.format().block_copy_size(None).unwrap();
+                        let padded_bytes_per_row = (size.width * bytes_per_texel)
+                            .next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+                        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                            label: Some(&format!("export_{}_buffer", texture_name)),
+                            size: padded_bytes_per_row as u64 * size.height as u64,
+                            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                            mapped_at_creation: false,
+                        });
+
+                        frame.encoder.copy_texture_to_buffer(
+                            texture.as_image_copy(),
+                            wgpu::TexelCopyBufferInfo {
+                                buffer: &buffer,
+                                layout: wgpu::TexelCopyBufferLayout {
+                                    offset: 0,
+                                    bytes_per_row: Some(padded_bytes_per_row),
+                                    rows_per_image: None,
+                                },
+                            },
+                            size,
+                        );
+
+                        PendingCopy {
+                            texture_name,
+                            buffer,
+                            bytes_per_texel,
+                            padded_bytes_per_row,
+                        }
+                    })
+                    .collect();
+            });
+        });
+    }
+
+    pub fn post_render(mut export: ResMut<Export>, surface_state: Res<SurfaceState>) {
+        if export.pending.is_empty() {
+            return;
+        }
+
+        let pending = std::mem::take(&mut export.pending);
+
+        for copy in &pending {
+            copy.buffer.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        }
+
+        if let Err(err) = surface_state.gpu.device.poll(wgpu::PollType::Wait) {
+            log::error!("Failed to read back the exported textures: {}", err);
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let directory = PathBuf::from(EXPORT_DIRECTORY).join(timestamp.to_string());
+
+        match export.write_all(&directory, &pending) {
+            Ok(()) => log::info!("Exported render to {}", directory.display()),
+            Err(err) => log::error!(
+                "Failed to export render to {}: {}",
+                directory.display(),
+                err
+            ),
+        }
+    }
+
+    fn write_all(&self, directory: &Path, pending: &[PendingCopy]) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+
+        // Unpadded rows of 32-bit channels, for every texture
+        let texels: Vec<(&str, Vec<[u32; 4]>)> = pending
+            .iter()
+            .map(|copy| (copy.texture_name, self.unpad(copy)))
+            .collect();
+
+        let find = |texture_name: &str| {
+            &texels
+                .iter()
+                .find(|(name, _)| *name == texture_name)
+                .unwrap()
+                .1
+        };
+
+        for (file_name, texture_name, channels) in OUTPUTS {
+            self.write_output(directory, file_name, find(texture_name), channels)?;
+        }
+
+        if pending
+            .iter()
+            .any(|copy| copy.texture_name == DENOISED_COLOR_TEXTURE)
+        {
+            self.write_output(
+                directory,
+                "denoised",
+                find(DENOISED_COLOR_TEXTURE),
+                Channels::Rgb,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Strips the row padding and splits the texels into their channels, reinterpreted later depending on the format
+    fn unpad(&self, copy: &PendingCopy) -> Vec<[u32; 4]> {
+        let data = copy.buffer.slice(..).get_mapped_range();
+        let channel_count = (copy.bytes_per_texel / 4) as usize;
+
+        let mut texels = Vec::with_capacity((self.size.width * self.size.height) as usize);
+
+        for row in data.chunks_exact(copy.padded_bytes_per_row as usize) {
+            let row = &row[..(self.size.width * copy.bytes_per_texel) as usize];
+
+            for texel in row.chunks_exact(copy.bytes_per_texel as usize) {
+                let mut channels = [0; 4];
+
+                for (channel, bytes) in channels
+                    .iter_mut()
+                    .zip(texel.chunks_exact(4))
+                    .take(channel_count)
+                {
+                    *channel = u32::from_le_bytes(bytes.try_into().unwrap());
+                }
+
+                texels.push(channels);
+            }
+        }
+
+        drop(data);
+        copy.buffer.unmap();
+
+        texels
+    }
+
+    fn write_output(
+        &self,
+        directory: &Path,
+        file_name: &str,
+        texels: &[[u32; 4]],
+        channels: Channels,
+    ) -> io::Result<()> {
+        let values: Vec<f32> = match channels {
+            Channels::Rgb => texels
+                .iter()
+                .flat_map(|texel| texel[..3].iter().map(|&channel| f32::from_bits(channel)))
+                .collect(),
+            Channels::Float(index) => texels
+                .iter()
+                .map(|texel| f32::from_bits(texel[index]))
+                .collect(),
+            Channels::Uint(index) => texels.iter().map(|texel| texel[index] as f32).collect(),
+            Channels::Variance => texels
+                .iter()
+                .map(|texel| {
+                    let mean = f32::from_bits(texel[0]);
+                    let mean_squared = f32::from_bits(texel[1]);
+
+                    (mean_squared - mean * mean).max(0.0)
+                })
+                .collect(),
+        };
+
+        let path = directory.join(format!("{}.pfm", file_name));
+        let color = matches!(channels, Channels::Rgb);
+
+        write_pfm(&path, self.size.width, self.size.height, color, &values)
+    }
+}
+
+// Writes a little endian PFM file. PFM stores rows bottom to top, while textures are stored top to bottom.
+fn write_pfm(path: &Path, width: u32, height: u32, color: bool, values: &[f32]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+
+    let magic = if color { "PF" } else { "Pf" };
+    let channel_count = if color { 3 } else { 1 };
+
+    // a negative scale means little endian
+    write!(file, "{}\n{} {}\n-1.0\n", magic, width, height)?;
+
+    let row_length = width as usize * channel_count;
+
+    for row in values.chunks_exact(row_length).rev() {
+        for value in row {
+            file.write_all(&value.to_le_bytes())?;
+        }
+    }
+
+    file.flush()
+}
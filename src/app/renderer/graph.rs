@@ -0,0 +1,407 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use bevy_ecs::{
+    resource::Resource,
+    world::{Mut, World},
+};
+
+use crate::app::renderer::{
+    atmosphere_lut::AtmosphereLutNode, denoise::DenoiseNode, display::DisplayNode,
+    material::MaterialNode, profiler::RenderProfiler, FrameRecord, RenderResolution, SurfaceState,
+};
+
+/*
+***Render graph***
+
+Passes are implemented as `RenderNode`s. Each node declares the textures it creates, reads and writes,
+and the graph takes care of:
+    - ordering the nodes so that every reader runs after the writers of the textures it reads
+    - allocating the textures at the render resolution, and reallocating them when it changes
+    - keeping the previous frame's contents around for textures that request history, by ping-ponging
+      between two textures
+    - recording a profiler scope for every node
+
+Adding a pass should only require implementing `RenderNode` and registering it in `RenderGraph::init`.
+*/
+
+pub struct GraphTextureDesc {
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+
+    // Whether the graph should keep a copy of the previous frame's contents of this texture around
+    pub history: bool,
+}
+
+#[derive(Default)]
+pub struct NodeBuilder {
+    creates: Vec<(Arc<str>, GraphTextureDesc)>,
+    reads: Vec<Arc<str>>,
+    writes: Vec<Arc<str>>,
+}
+
+impl NodeBuilder {
+    /// Declares a new graph texture, written to by this node.
+    pub fn create_texture(&mut self, name: &str, desc: GraphTextureDesc) {
+        let name: Arc<str> = Arc::from(name);
+
+        self.creates.push((name.clone(), desc));
+        self.writes.push(name);
+    }
+
+    /// Declares that this node reads a graph texture, so it runs after every node that writes to it.
+    pub fn read_texture(&mut self, name: &str) {
+        self.reads.push(Arc::from(name));
+    }
+}
+
+pub struct GraphResources {
+    descs: HashMap<Arc<str>, GraphTextureDesc>,
+
+    // Textures with history are allocated as a pair that is swapped every time the node creating them runs,
+    // so the previous frame's contents stay available without copying them around.
+    textures: HashMap<Arc<str>, Vec<wgpu::Texture>>,
+    current_indices: HashMap<Arc<str>, usize>,
+
+    size: wgpu::Extent3d,
+}
+
+impl GraphResources {
+    fn new() -> Self {
+        Self {
+            descs: HashMap::new(),
+            textures: HashMap::new(),
+            current_indices: HashMap::new(),
+            size: wgpu::Extent3d::default(),
+        }
+    }
+
+    fn allocate(&mut self, device: &wgpu::Device, size: wgpu::Extent3d) {
+        self.size = size;
+
+        self.textures.clear();
+        self.current_indices.clear();
+
+        for (name, desc) in &self.descs {
+            let count = if desc.history { 2 } else { 1 };
+
+            let textures = (0..count)
+                .map(|index| {
+                    let label = format!("{}_{}", name, index);
+
+                    device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some(&label),
+                        size,
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: desc.format,
+                        usage: desc.usage,
+                        view_formats: &[],
+                    })
+                })
+                .collect();
+
+            self.textures.insert(name.clone(), textures);
+            self.current_indices.insert(name.clone(), 0);
+        }
+    }
+
+    // Makes the current texture of a ping-pong pair the history texture, and vice versa
+    fn swap(&mut self, name: &str) {
+        if !self.descs[name].history {
+            return;
+        }
+
+        let current_index = self.current_indices.get_mut(name).unwrap();
+        *current_index = 1 - *current_index;
+    }
+
+    fn textures(&self, name: &str) -> &[wgpu::Texture] {
+        self.textures
+            .get(name)
+            .unwrap_or_else(|| panic!("Render graph texture {} does not exist", name))
+    }
+
+    pub fn size(&self) -> wgpu::Extent3d {
+        self.size
+    }
+
+    pub fn has_history(&self, name: &str) -> bool {
+        self.descs[name].history
+    }
+
+    /// Index of the texture written this frame, for textures with history. Nodes can use this to pick
+    /// between bind groups built with `texture_at`.
+    pub fn current_index(&self, name: &str) -> usize {
+        self.current_indices[name]
+    }
+
+    /// The texture at `index` of a ping-pong pair, regardless of which one is current.
+    pub fn texture_at(&self, name: &str, index: usize) -> &wgpu::Texture {
+        &self.textures(name)[index]
+    }
+
+    pub fn texture(&self, name: &str) -> &wgpu::Texture {
+        self.texture_at(name, self.current_index(name))
+    }
+
+    pub fn view_at(&self, name: &str, index: usize) -> wgpu::TextureView {
+        self.texture_at(name, index)
+            .create_view(&Default::default())
+    }
+}
+
+pub struct NodeContext<'a> {
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub surface_texture_view: &'a wgpu::TextureView,
+    pub resources: &'a GraphResources,
+
+    time_query: &'a mut wgputil::profile::TimeQuery,
+}
+
+impl NodeContext<'_> {
+    /// Begins a compute pass that writes to this node's profiler scope.
+    pub fn begin_compute_pass(&mut self, label: &str) -> wgpu::ComputePass<'_> {
+        self.encoder
+            .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(label),
+                timestamp_writes: Some(self.time_query.compute_timestamp_writes()),
+            })
+    }
+
+    /// Begins a render pass that writes to this node's profiler scope.
+    pub fn begin_render_pass(
+        &mut self,
+        label: &str,
+        color_attachments: &[Option<wgpu::RenderPassColorAttachment>],
+    ) -> wgpu::RenderPass<'_> {
+        self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments,
+            depth_stencil_attachment: None,
+            timestamp_writes: Some(self.time_query.render_timestamp_writes()),
+            occlusion_query_set: None,
+        })
+    }
+}
+
+pub trait RenderNode: Send + Sync + 'static {
+    /// Name of the node, also used for its profiler scope.
+    fn name(&self) -> &'static str;
+
+    /// Declares the graph textures this node creates, reads and writes.
+    fn setup(&self, builder: &mut NodeBuilder);
+
+    /// Called every time the graph textures have been reallocated after a resize, so the node can
+    /// recreate anything that references them (e.g. bind groups).
+    fn prepare(&mut self, _world: &mut World, _resources: &GraphResources) {}
+
+    /// Whether the node should run this frame. Disabled nodes don't record anything, and the history of the
+    /// textures they create isn't advanced, so readers keep seeing their last output.
+    fn enabled(&self, _world: &World) -> bool {
+        true
+    }
+
+    /// Records the node's commands for this frame.
+    fn record(&mut self, world: &World, ctx: &mut NodeContext);
+}
+
+struct NodeEntry {
+    node: Box<dyn RenderNode>,
+    creates: Vec<Arc<str>>,
+    reads: Vec<Arc<str>>,
+    writes: Vec<Arc<str>>,
+    time_query_index: usize,
+}
+
+#[derive(Resource)]
+pub struct RenderGraph {
+    nodes: Vec<NodeEntry>,
+    order: Vec<usize>,
+    resources: GraphResources,
+}
+
+impl RenderGraph {
+    fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            order: Vec::new(),
+            resources: GraphResources::new(),
+        }
+    }
+
+    pub fn resources(&self) -> &GraphResources {
+        &self.resources
+    }
+
+    fn add_node(&mut self, world: &mut World, node: impl RenderNode) {
+        let mut builder = NodeBuilder::default();
+        node.setup(&mut builder);
+
+        let mut creates = Vec::new();
+
+        for (name, desc) in builder.creates {
+            if self.resources.descs.insert(name.clone(), desc).is_some() {
+                panic!("Render graph texture {} was created more than once", name);
+            }
+
+            creates.push(name);
+        }
+
+        let time_query_index = world.resource_scope(|world, mut profiler: Mut<RenderProfiler>| {
+            profiler.push(&world.resource::<SurfaceState>().gpu, node.name())
+        });
+
+        self.nodes.push(NodeEntry {
+            node: Box::new(node),
+            creates,
+            reads: builder.reads,
+            writes: builder.writes,
+            time_query_index,
+        });
+    }
+
+    // Orders the nodes so that every node runs after the nodes writing to the textures it reads.
+    // Nodes that don't depend on each other keep the order they were added in.
+    fn compile(&mut self) {
+        for entry in &self.nodes {
+            for name in entry.reads.iter().chain(&entry.writes) {
+                if !self.resources.descs.contains_key(name) {
+                    panic!(
+                        "Render graph node {} uses texture {}, which no node creates",
+                        entry.node.name(),
+                        name
+                    );
+                }
+            }
+        }
+
+        let dependencies: Vec<HashSet<usize>> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                self.nodes
+                    .iter()
+                    .enumerate()
+                    .filter(|&(other, other_entry)| {
+                        other != index
+                            && other_entry
+                                .writes
+                                .iter()
+                                .any(|name| entry.reads.contains(name))
+                    })
+                    .map(|(other, _)| other)
+                    .collect()
+            })
+            .collect();
+
+        self.order.clear();
+
+        while self.order.len() < self.nodes.len() {
+            let next = (0..self.nodes.len()).find(|index| {
+                !self.order.contains(index)
+                    && dependencies[*index]
+                        .iter()
+                        .all(|dependency| self.order.contains(dependency))
+            });
+
+            match next {
+                Some(index) => self.order.push(index),
+                None => panic!("Render graph contains a dependency cycle"),
+            }
+        }
+    }
+
+    fn size(world: &World) -> wgpu::Extent3d {
+        let render_resolution = world.resource::<RenderResolution>();
+
+        wgpu::Extent3d {
+            width: render_resolution.width,
+            height: render_resolution.height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    fn allocate(&mut self, world: &World) {
+        self.resources.allocate(
+            &world.resource::<SurfaceState>().gpu.device,
+            Self::size(world),
+        );
+    }
+
+    pub fn init(world: &mut World) {
+        let mut graph = RenderGraph::new();
+
+        // Passes are listed here, the graph figures out the order they run in. The atmosphere lookup tables aren't
+        // graph textures, so their pass has to be listed before the material pass reading them.
+        graph.add_node(world, AtmosphereLutNode::default());
+        graph.add_node(world, MaterialNode);
+        graph.add_node(world, DenoiseNode);
+        graph.add_node(world, DisplayNode);
+
+        graph.compile();
+        graph.allocate(world);
+
+        world.insert_resource(graph);
+    }
+
+    pub fn on_resize(world: &mut World) {
+        world.resource_scope(|world, mut graph: Mut<RenderGraph>| {
+            let graph = &mut *graph;
+
+            // Resizes that don't change the render resolution (e.g. moving the menu panels) keep the textures
+            if graph.resources.size() == Self::size(world) {
+                return;
+            }
+
+            graph.allocate(world);
+
+            // Let every node recreate whatever references the old textures
+            for entry in &mut graph.nodes {
+                entry.node.prepare(world, &graph.resources);
+            }
+        });
+    }
+
+    pub fn draw(world: &mut World) {
+        world.resource_scope(|world, mut graph: Mut<RenderGraph>| {
+            world.resource_scope(|world, mut profiler: Mut<RenderProfiler>| {
+                world.resource_scope(|world, mut frame: Mut<FrameRecord>| {
+                    graph.record(world, &mut profiler, &mut frame);
+                });
+            });
+        });
+    }
+
+    fn record(&mut self, world: &World, profiler: &mut RenderProfiler, frame: &mut FrameRecord) {
+        let frame = &mut **frame;
+
+        for &index in &self.order {
+            let entry = &mut self.nodes[index];
+
+            if !entry.node.enabled(world) {
+                continue;
+            }
+
+            // Last frame's output becomes the history, and the node writes to the other texture of the pair
+            for name in &entry.creates {
+                self.resources.swap(name);
+            }
+
+            let (_, time_query) = &mut profiler.time_queries[entry.time_query_index];
+
+            let mut ctx = NodeContext {
+                encoder: &mut frame.encoder,
+                surface_texture_view: &frame.surface_texture_view,
+                resources: &self.resources,
+                time_query,
+            };
+
+            entry.node.record(world, &mut ctx);
+        }
+    }
+}
@@ -2,130 +2,316 @@ use std::{collections::HashMap, sync::Arc};
 
 use bevy_ecs::{
     resource::Resource,
-    system::{Commands, Local, Res, ResMut},
+    system::{Commands, Res, ResMut},
+    world::{Mut, World},
 };
 use glam::UVec3;
+use gpu_bytes::AsStd430;
 use wgputil::shader::ShaderSource;
 
 use crate::{
     app::{
+        atmosphere::binding::AtmosphereBinding,
         camera::binding::ScreenBinding,
         lookup::SpectrumBinding,
+        menu::Menu,
         object::binding::ObjectBinding,
-        renderer::{profiler::RenderProfiler, FrameRecord, RendererViewport, SurfaceState},
+        renderer::{
+            accumulation::{Accumulation, SampleSettings},
+            graph::{
+                GraphResources, GraphTextureDesc, NodeBuilder, NodeContext, RenderGraph, RenderNode,
+            },
+            SurfaceState,
+        },
     },
     util,
 };
 
+pub const MATERIAL_COLOR_TEXTURE: &str = "material_color";
+pub const MATERIAL_MOMENTS_TEXTURE: &str = "material_moments";
+pub const MATERIAL_ALBEDO_TEXTURE: &str = "material_albedo";
+pub const MATERIAL_NORMAL_DEPTH_TEXTURE: &str = "material_normal_depth";
+pub const MATERIAL_POSITION_TEXTURE: &str = "material_position";
+pub const MATERIAL_IDS_TEXTURE: &str = "material_ids";
+
+// How the probability of a path surviving russian roulette is picked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouletteStrategy {
+    // Paths are only terminated by the depth limits
+    Disabled,
+    // Survival probability is the path throughput, so paths carrying little energy are terminated sooner
+    Throughput,
+    // Every bounce survives with the same probability
+    Fixed,
+}
+
+// Where the radiance of rays leaving the scene comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkyMode {
+    // Delta tracking through the atmosphere, the reference for final renders
+    PathTraced,
+    // Lookup tables recomputed whenever the atmosphere changes, much cheaper for interactive navigation
+    Precomputed,
+    // An HDR image around the scene instead of the atmosphere, importance sampled from diffuse surfaces
+    Environment,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct PathtraceSettings {
+    // Maximum number of bounces of a path
+    pub max_depth: u32,
+    // Russian roulette only starts after this many bounces
+    pub min_roulette_depth: u32,
+    pub roulette_strategy: RouletteStrategy,
+    // Used by the fixed strategy
+    pub survival_probability: f32,
+
+    // Maximum number of bounces off of each kind of lobe
+    pub max_diffuse_depth: u32,
+    pub max_specular_depth: u32,
+    pub max_transmission_depth: u32,
+
+    pub sky_mode: SkyMode,
+
+    // Index into `EnvironmentMaps`, used by the environment sky mode
+    pub environment_map: Option<usize>,
+    // Turns the map around the vertical axis, in degrees
+    pub environment_rotation: f32,
+    pub environment_intensity: f32,
+}
+
+impl Default for PathtraceSettings {
+    fn default() -> Self {
+        Self {
+            max_depth: 100,
+            min_roulette_depth: 3,
+            roulette_strategy: RouletteStrategy::Throughput,
+            survival_probability: 0.8,
+            max_diffuse_depth: 100,
+            max_specular_depth: 100,
+            max_transmission_depth: 100,
+            sky_mode: SkyMode::PathTraced,
+            environment_map: None,
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+        }
+    }
+}
+
+// Alternate entry points of pathtrace.slang that write a visualization of the scene instead of the path traced
+// color, for tracking down rendering issues
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathtraceDebugMode {
+    None,
+    Normals,
+    HitDistance,
+    PrimitiveTests,
+    MaterialType,
+    NanInf,
+    RouletteDepth,
+}
+
+impl PathtraceDebugMode {
+    pub const ALL: [PathtraceDebugMode; 7] = [
+        PathtraceDebugMode::None,
+        PathtraceDebugMode::Normals,
+        PathtraceDebugMode::HitDistance,
+        PathtraceDebugMode::PrimitiveTests,
+        PathtraceDebugMode::MaterialType,
+        PathtraceDebugMode::NanInf,
+        PathtraceDebugMode::RouletteDepth,
+    ];
+
+    /// Name of the entry point in pathtrace.slang, which the material pipeline is registered under.
+    pub fn entry_point(self) -> &'static str {
+        match self {
+            PathtraceDebugMode::None => "compute",
+            PathtraceDebugMode::Normals => "debugNormals",
+            PathtraceDebugMode::HitDistance => "debugHitDistance",
+            PathtraceDebugMode::PrimitiveTests => "debugPrimitiveTests",
+            PathtraceDebugMode::MaterialType => "debugMaterialType",
+            PathtraceDebugMode::NanInf => "debugNanInf",
+            PathtraceDebugMode::RouletteDepth => "debugRouletteDepth",
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct MaterialTextures {
-    pub current_texture: wgpu::Texture,
-    pub previous_texture: wgpu::Texture,
-
     pub bind_group_layout: wgpu::BindGroupLayout,
-    pub bind_group: wgpu::BindGroup,
+
+    // The material color texture is ping-ponged, so there is one bind group for each texture of the pair
+    // being the one written to. Indexed by the current index of the material color texture.
+    pub bind_groups: [wgpu::BindGroup; 2],
+
+    // Single counter of the samples rejected for being NaN or infinite, cleared every frame
+    pub rejected_samples_buffer: wgpu::Buffer,
 }
 
 impl MaterialTextures {
     pub fn init(
         mut commands: Commands,
         surface_state: Res<SurfaceState>,
-        renderer_viewport: Res<RendererViewport>,
+        render_graph: Res<RenderGraph>,
     ) {
         let gpu = &surface_state.gpu;
+        let resources = render_graph.resources();
+
+        let storage_texture = |name: &str, index: usize, access: wgpu::StorageTextureAccess| {
+            let texture = resources.texture_at(name, index);
+            (
+                texture.create_view(&Default::default()),
+                texture.format(),
+                access,
+            )
+        };
 
-        let (current_desc, previous_desc) = Self::desc(&renderer_viewport);
+        let textures = [
+            storage_texture(
+                MATERIAL_COLOR_TEXTURE,
+                0,
+                wgpu::StorageTextureAccess::WriteOnly,
+            ),
+            storage_texture(
+                MATERIAL_COLOR_TEXTURE,
+                1,
+                wgpu::StorageTextureAccess::ReadOnly,
+            ),
+            storage_texture(
+                MATERIAL_MOMENTS_TEXTURE,
+                0,
+                wgpu::StorageTextureAccess::WriteOnly,
+            ),
+            storage_texture(
+                MATERIAL_MOMENTS_TEXTURE,
+                1,
+                wgpu::StorageTextureAccess::ReadOnly,
+            ),
+            storage_texture(
+                MATERIAL_ALBEDO_TEXTURE,
+                0,
+                wgpu::StorageTextureAccess::WriteOnly,
+            ),
+            storage_texture(
+                MATERIAL_NORMAL_DEPTH_TEXTURE,
+                0,
+                wgpu::StorageTextureAccess::WriteOnly,
+            ),
+            storage_texture(
+                MATERIAL_NORMAL_DEPTH_TEXTURE,
+                1,
+                wgpu::StorageTextureAccess::ReadOnly,
+            ),
+            storage_texture(
+                MATERIAL_POSITION_TEXTURE,
+                0,
+                wgpu::StorageTextureAccess::WriteOnly,
+            ),
+            storage_texture(
+                MATERIAL_IDS_TEXTURE,
+                0,
+                wgpu::StorageTextureAccess::WriteOnly,
+            ),
+        ];
+
+        let rejected_samples_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("rejected_samples_buffer"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
 
-        let current_texture = gpu.device.create_texture(&current_desc);
-        let previous_texture = gpu.device.create_texture(&previous_desc);
+        let mut entries: Vec<_> = textures
+            .iter()
+            .map(|(view, format, access)| {
+                wgputil::binding::bind_storage_texture(
+                    view,
+                    *format,
+                    wgpu::TextureViewDimension::D2,
+                    *access,
+                )
+            })
+            .collect();
 
-        let current_texture_view = current_texture.create_view(&Default::default());
-        let previous_texture_view = previous_texture.create_view(&Default::default());
+        entries.push(wgputil::binding::bind_buffer_storage(
+            &rejected_samples_buffer,
+            false,
+        ));
 
         let (bind_group_layout, bind_group) = wgputil::binding::create_sequential_linked(
             &gpu.device,
             "material_texture_binding",
-            &[
-                wgputil::binding::bind_storage_texture(
-                    &current_texture_view,
-                    current_texture.format(),
-                    wgpu::TextureViewDimension::D2,
-                    wgpu::StorageTextureAccess::WriteOnly,
-                ),
-                wgputil::binding::bind_storage_texture(
-                    &previous_texture_view,
-                    previous_texture.format(),
-                    wgpu::TextureViewDimension::D2,
-                    wgpu::StorageTextureAccess::ReadOnly,
-                ),
-            ],
+            &entries,
+        );
+
+        let swapped_bind_group = Self::create_bind_group(
+            &gpu.device,
+            &bind_group_layout,
+            resources,
+            &rejected_samples_buffer,
+            1,
         );
 
         commands.insert_resource(Self {
-            current_texture,
-            previous_texture,
             bind_group_layout,
-            bind_group,
+            bind_groups: [bind_group, swapped_bind_group],
+            rejected_samples_buffer,
         });
     }
 
-    pub fn on_resize(
-        mut material_textures: ResMut<MaterialTextures>,
-        surface_state: Res<SurfaceState>,
-        renderer_viewport: Res<RendererViewport>,
-    ) {
-        let gpu = &surface_state.gpu;
-
-        let (current_desc, previous_desc) = Self::desc(&renderer_viewport);
-
-        let current_texture = gpu.device.create_texture(&current_desc);
-        let previous_texture = gpu.device.create_texture(&previous_desc);
-
-        let current_texture_view = current_texture.create_view(&Default::default());
-        let previous_texture_view = previous_texture.create_view(&Default::default());
-
-        let bind_group = wgputil::binding::create_sequential_with_layout(
-            &surface_state.gpu.device,
+    fn create_bind_group(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        resources: &GraphResources,
+        rejected_samples_buffer: &wgpu::Buffer,
+        current_index: usize,
+    ) -> wgpu::BindGroup {
+        let previous_index = 1 - current_index;
+
+        // The textures with history are created by the same node, so they are always swapped together
+        let views = [
+            resources.view_at(MATERIAL_COLOR_TEXTURE, current_index),
+            resources.view_at(MATERIAL_COLOR_TEXTURE, previous_index),
+            resources.view_at(MATERIAL_MOMENTS_TEXTURE, current_index),
+            resources.view_at(MATERIAL_MOMENTS_TEXTURE, previous_index),
+            resources.view_at(MATERIAL_ALBEDO_TEXTURE, 0),
+            resources.view_at(MATERIAL_NORMAL_DEPTH_TEXTURE, current_index),
+            resources.view_at(MATERIAL_NORMAL_DEPTH_TEXTURE, previous_index),
+            resources.view_at(MATERIAL_POSITION_TEXTURE, 0),
+            resources.view_at(MATERIAL_IDS_TEXTURE, 0),
+        ];
+
+        let mut binding_resources: Vec<_> = views
+            .iter()
+            .map(wgpu::BindingResource::TextureView)
+            .collect();
+
+        binding_resources.push(rejected_samples_buffer.as_entire_binding());
+
+        wgputil::binding::create_sequential_with_layout(
+            device,
             "material_texture_binding",
-            &material_textures.bind_group_layout,
-            &[
-                wgpu::BindingResource::TextureView(&current_texture_view),
-                wgpu::BindingResource::TextureView(&previous_texture_view),
-            ],
-        );
-
-        material_textures.current_texture = current_texture;
-        material_textures.previous_texture = previous_texture;
-        material_textures.bind_group = bind_group;
+            bind_group_layout,
+            &binding_resources,
+        )
     }
 
-    fn desc(
-        renderer_viewport: &RendererViewport,
-    ) -> (wgpu::TextureDescriptor, wgpu::TextureDescriptor) {
-        let current_desc = wgpu::TextureDescriptor {
-            label: Some("material_pass_current_texture"),
-            size: wgpu::Extent3d {
-                width: renderer_viewport.get_width(),
-                height: renderer_viewport.get_height(),
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba32Float,
-            usage: wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_SRC,
-            view_formats: &[],
-        };
-
-        let previous_desc = wgpu::TextureDescriptor {
-            label: Some("material_pass_previous_texture"),
-            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_DST,
-            ..current_desc
-        };
+    fn rebuild(&mut self, device: &wgpu::Device, resources: &GraphResources) {
+        self.bind_groups = [0, 1].map(|current_index| {
+            Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                resources,
+                &self.rejected_samples_buffer,
+                current_index,
+            )
+        });
+    }
 
-        (current_desc, previous_desc)
+    pub fn bind_group(&self, resources: &GraphResources) -> &wgpu::BindGroup {
+        &self.bind_groups[resources.current_index(MATERIAL_COLOR_TEXTURE)]
     }
 }
 
@@ -143,6 +329,7 @@ impl MaterialPipelines {
         object_binding: Res<ObjectBinding>,
         spectrum_binding: Res<SpectrumBinding>,
         material_textures: Res<MaterialTextures>,
+        atmosphere_binding: Res<AtmosphereBinding>,
     ) {
         let gpu = &surface_state.gpu;
 
@@ -155,33 +342,39 @@ impl MaterialPipelines {
                     &object_binding.bind_group_layout,
                     &spectrum_binding.bind_group_layout,
                     &material_textures.bind_group_layout,
+                    &atmosphere_binding.bind_group_layout,
+                    &object_binding.image_textures.bind_group_layout,
+                    &atmosphere_binding.environment_maps.bind_group_layout,
                 ],
-                push_constant_ranges: &[],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..SampleSettings::SIZE,
+                }],
             });
 
         let mut pipelines = HashMap::new();
 
-        let pipeline_paths = ["pathtrace.slang"];
-        let active_pipeline = Arc::from(pipeline_paths[0]);
+        // The debug modes are entry points of the same shader, so every pipeline is registered by its entry point
+        let source = ShaderSource::load_spirv(util::shader_path("pathtrace.slang"));
+        let module = wgputil::shader::create(&gpu.device, &source).unwrap();
 
-        for pipeline_path in pipeline_paths {
-            let path = util::shader_path(pipeline_path);
+        let active_pipeline = Arc::from(PathtraceDebugMode::None.entry_point());
 
-            let source = ShaderSource::load_spirv(&path);
-            let module = wgputil::shader::create(&gpu.device, &source).unwrap();
+        for debug_mode in PathtraceDebugMode::ALL {
+            let entry_point = debug_mode.entry_point();
 
             let pipeline = gpu
                 .device
                 .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                    label: Some(pipeline_path),
+                    label: Some(entry_point),
                     layout: Some(&layout),
                     module: &module,
-                    entry_point: Some("compute"),
+                    entry_point: Some(entry_point),
                     compilation_options: Default::default(),
                     cache: None,
                 });
 
-            pipelines.insert(Arc::from(pipeline_path), pipeline);
+            pipelines.insert(Arc::from(entry_point), pipeline);
         }
 
         commands.insert_resource(Self {
@@ -194,65 +387,163 @@ impl MaterialPipelines {
         &self.pipelines[&self.active_pipeline]
     }
 
-    #[expect(unused)]
     pub fn set_active_pipeline(&mut self, active_pipeline: &str) {
         self.active_pipeline = Arc::from(active_pipeline);
     }
-}
 
-#[expect(clippy::too_many_arguments)]
-pub fn draw(
-    mut time_query_index: Local<Option<usize>>,
+    pub fn update(mut material_pipelines: ResMut<MaterialPipelines>, menu: Res<Menu>) {
+        let entry_point = menu.settings.pathtrace_debug.entry_point();
 
-    surface_state: Res<SurfaceState>,
-    mut profiler: ResMut<RenderProfiler>,
-    mut frame: ResMut<FrameRecord>,
+        if *material_pipelines.active_pipeline != *entry_point {
+            material_pipelines.set_active_pipeline(entry_point);
+        }
+    }
+}
 
-    screen_binding: Res<ScreenBinding>,
-    object_binding: Res<ObjectBinding>,
-    spectrum_binding: Res<SpectrumBinding>,
-    material_textures: Res<MaterialTextures>,
+pub struct MaterialNode;
 
-    material_pipelines: Res<MaterialPipelines>,
-) {
-    if time_query_index.is_none() {
-        *time_query_index = Some(profiler.push(&surface_state.gpu, "material_pass"));
+impl RenderNode for MaterialNode {
+    fn name(&self) -> &'static str {
+        "material_pass"
     }
 
-    let (_, time_query) = &mut profiler.time_queries[time_query_index.unwrap()];
+    fn setup(&self, builder: &mut NodeBuilder) {
+        builder.create_texture(
+            MATERIAL_COLOR_TEXTURE,
+            GraphTextureDesc {
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                history: true,
+            },
+        );
 
-    // Copy current texture to previous texture
-    frame.encoder.copy_texture_to_texture(
-        material_textures.current_texture.as_image_copy(),
-        material_textures.previous_texture.as_image_copy(),
-        material_textures.current_texture.size(),
-    );
+        // The remaining textures are arbitrary output variables, which can be viewed in the display pass and
+        // exported, so they need to be copyable
+        let aov_usage = wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC;
+
+        builder.create_texture(
+            MATERIAL_MOMENTS_TEXTURE,
+            GraphTextureDesc {
+                format: wgpu::TextureFormat::Rg32Float,
+                usage: aov_usage,
+                history: true,
+            },
+        );
 
-    let mut compute_pass = frame
-        .encoder
-        .begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("material_pass"),
-            timestamp_writes: Some(time_query.compute_timestamp_writes()),
-        });
+        builder.create_texture(
+            MATERIAL_ALBEDO_TEXTURE,
+            GraphTextureDesc {
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: aov_usage,
+                history: false,
+            },
+        );
 
-    compute_pass.set_bind_group(0, &screen_binding.bind_group, &[]);
-    compute_pass.set_bind_group(1, &object_binding.bind_group, &[]);
-    compute_pass.set_bind_group(2, &spectrum_binding.bind_group, &[]);
-    compute_pass.set_bind_group(3, &material_textures.bind_group, &[]);
+        // Reprojection compares against last frame's normals and depths to reject history
+        builder.create_texture(
+            MATERIAL_NORMAL_DEPTH_TEXTURE,
+            GraphTextureDesc {
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: aov_usage,
+                history: true,
+            },
+        );
 
-    compute_pass.set_pipeline(material_pipelines.get_active_pipeline());
+        builder.create_texture(
+            MATERIAL_POSITION_TEXTURE,
+            GraphTextureDesc {
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: aov_usage,
+                history: false,
+            },
+        );
 
-    let workgroup_sizes = UVec3::new(8, 8, 1);
-    let dimensions = UVec3::new(
-        material_textures.current_texture.width(),
-        material_textures.current_texture.height(),
-        1,
-    );
+        builder.create_texture(
+            MATERIAL_IDS_TEXTURE,
+            GraphTextureDesc {
+                format: wgpu::TextureFormat::Rgba32Uint,
+                usage: aov_usage,
+                history: false,
+            },
+        );
+    }
 
-    let mut workgroups = dimensions / workgroup_sizes;
+    fn prepare(&mut self, world: &mut World, resources: &GraphResources) {
+        world.resource_scope(|world, mut material_textures: Mut<MaterialTextures>| {
+            let surface_state = world.resource::<SurfaceState>();
+            material_textures.rebuild(&surface_state.gpu.device, resources);
+        });
+    }
 
-    // Add an extra workgroup in each dimension if the number we calculated doesn't cover the whole dimensions
-    workgroups += (dimensions % workgroups) & UVec3::ONE;
+    fn enabled(&self, world: &World) -> bool {
+        world.resource::<Accumulation>().active
+    }
 
-    compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+    fn record(&mut self, world: &World, ctx: &mut NodeContext) {
+        let accumulation = world.resource::<Accumulation>();
+        let screen_binding = world.resource::<ScreenBinding>();
+        let object_binding = world.resource::<ObjectBinding>();
+        let spectrum_binding = world.resource::<SpectrumBinding>();
+        let material_textures = world.resource::<MaterialTextures>();
+        let material_pipelines = world.resource::<MaterialPipelines>();
+        let atmosphere_binding = world.resource::<AtmosphereBinding>();
+
+        let resources = ctx.resources;
+        let size = resources.size();
+
+        ctx.encoder
+            .clear_buffer(&material_textures.rejected_samples_buffer, 0, None);
+
+        let mut compute_pass = ctx.begin_compute_pass("material_pass");
+
+        compute_pass.set_bind_group(0, &screen_binding.bind_group, &[]);
+        compute_pass.set_bind_group(1, &object_binding.bind_group, &[]);
+        compute_pass.set_bind_group(2, &spectrum_binding.bind_group, &[]);
+        compute_pass.set_bind_group(3, material_textures.bind_group(resources), &[]);
+        compute_pass.set_bind_group(4, &atmosphere_binding.bind_group, &[]);
+        compute_pass.set_bind_group(5, &object_binding.image_textures.bind_group, &[]);
+        compute_pass.set_bind_group(6, &atmosphere_binding.environment_maps.bind_group, &[]);
+
+        compute_pass.set_pipeline(material_pipelines.get_active_pipeline());
+        compute_pass.set_push_constants(0, accumulation.sample_settings.as_std430().as_slice());
+
+        let workgroup_sizes = UVec3::new(8, 8, 1);
+        let dimensions = UVec3::new(size.width, size.height, 1);
+
+        let workgroups = (dimensions + workgroup_sizes - UVec3::ONE) / workgroup_sizes;
+
+        compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+        drop(compute_pass);
+
+        // Copy a single texel so the sample count stored in the frame age channel can be read back
+        ctx.encoder.copy_texture_to_buffer(
+            resources.texture(MATERIAL_COLOR_TEXTURE).as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &accumulation.readback_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        // The rejected sample count goes right after the texel
+        ctx.encoder.copy_buffer_to_buffer(
+            &material_textures.rejected_samples_buffer,
+            0,
+            &accumulation.readback_buffer,
+            16,
+            4,
+        );
+    }
 }
@@ -1,14 +1,23 @@
 use bevy_ecs::{
+    change_detection::DetectChangesMut,
     resource::Resource,
-    system::{Commands, Res},
+    system::{Commands, Res, ResMut},
 };
 use glam::UVec2;
 use gpu_bytes_derive::AsStd430;
 use winit::dpi::PhysicalSize;
 
-use crate::ecs::ResourceWrapper;
+use crate::{
+    app::menu::{Menu, Settings},
+    ecs::ResourceWrapper,
+};
 
+pub mod accumulation;
+pub mod atmosphere_lut;
+pub mod denoise;
 pub mod display;
+pub mod export;
+pub mod graph;
 pub mod material;
 pub mod profiler;
 
@@ -30,13 +39,6 @@ impl RendererViewport {
         self.end.y - self.start.y
     }
 
-    pub fn get_size(&self) -> PhysicalSize<u32> {
-        PhysicalSize {
-            width: self.get_width(),
-            height: self.get_height(),
-        }
-    }
-
     pub fn init(mut commands: Commands, surface_state: Res<SurfaceState>) {
         commands.insert_resource(Self {
             start: UVec2::new(0, 0),
@@ -47,3 +49,82 @@ impl RendererViewport {
         })
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolutionMode {
+    // A percentage of the window size
+    Scaled,
+    // An exact resolution regardless of the window size, e.g. for final frames
+    Fixed,
+}
+
+// The render textures take around 200 bytes per pixel between the material, denoiser and output buffers, so this
+// keeps them to a few GB
+const MAX_RENDER_PIXELS: u32 = 4096 * 4096;
+
+// Resolution the scene is rendered at. It follows the window size rather than the renderer viewport, so moving the
+// menu panels around doesn't reallocate the render textures and throw away accumulated samples. The display pass
+// scales the image to fit the viewport.
+#[derive(Resource, PartialEq, Eq)]
+pub struct RenderResolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderResolution {
+    fn from_settings(surface_state: &SurfaceState, settings: &Settings) -> Self {
+        let max_size = surface_state.gpu.device.limits().max_texture_dimension_2d;
+
+        let size = match settings.resolution_mode {
+            ResolutionMode::Scaled => {
+                let window_size = UVec2::new(
+                    surface_state.viewport_size.width,
+                    surface_state.viewport_size.height,
+                );
+                let scale = settings.render_scale as f32 / 100.0;
+
+                (window_size.as_vec2() * scale).round().as_uvec2()
+            }
+            ResolutionMode::Fixed => settings.fixed_resolution,
+        };
+
+        let size = size.clamp(UVec2::ONE, UVec2::splat(max_size));
+
+        // Scaled down to fit, keeping the aspect ratio
+        let pixels = size.x as f32 * size.y as f32;
+        let size = if pixels > MAX_RENDER_PIXELS as f32 {
+            (size.as_vec2() * (MAX_RENDER_PIXELS as f32 / pixels).sqrt())
+                .floor()
+                .as_uvec2()
+                .max(UVec2::ONE)
+        } else {
+            size
+        };
+
+        Self {
+            width: size.x,
+            height: size.y,
+        }
+    }
+
+    pub fn get_size(&self) -> PhysicalSize<u32> {
+        PhysicalSize {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn init(mut commands: Commands, surface_state: Res<SurfaceState>) {
+        // The menu isn't initialized yet, so start out with the default settings
+        commands.insert_resource(Self::from_settings(&surface_state, &Settings::default()));
+    }
+
+    pub fn on_resize(
+        mut render_resolution: ResMut<RenderResolution>,
+        surface_state: Res<SurfaceState>,
+        menu: Res<Menu>,
+    ) {
+        // Only trigger change detection if the resolution actually changed
+        render_resolution.set_if_neq(Self::from_settings(&surface_state, &menu.settings));
+    }
+}
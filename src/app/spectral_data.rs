@@ -0,0 +1,309 @@
+use std::{fs, io, path::Path};
+
+use crate::util;
+
+// The atlas uses the same wavelengths as the wavelength to XYZ LUT, 360nm to 830nm in 1nm steps
+pub const SPECTRAL_ATLAS_START: f32 = 360.0;
+pub const SPECTRAL_ATLAS_WIDTH: u32 = 471;
+
+// Tabulated emitter spectra are scaled to this value at 560nm, like CIE illuminants, so their brightness is close to
+// a white RGB emitter of the same strength
+const ILLUMINANT_NORMALIZATION_WAVELENGTH: f32 = 560.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpectrumKind {
+    // Spectral power distribution of an emitter
+    Illuminant,
+    // Reflectance of a surface, within [0, 1]
+    Reflectance,
+    // Complex refractive index (n, k) of a metal
+    Conductor,
+}
+
+impl SpectrumKind {
+    pub const ALL: [SpectrumKind; 3] = [
+        SpectrumKind::Illuminant,
+        SpectrumKind::Reflectance,
+        SpectrumKind::Conductor,
+    ];
+
+    // Directory in assets/spectra that holds the CSV files of this kind
+    fn directory(self) -> &'static str {
+        match self {
+            SpectrumKind::Illuminant => "illuminants",
+            SpectrumKind::Reflectance => "reflectances",
+            SpectrumKind::Conductor => "conductors",
+        }
+    }
+
+    fn column_count(self) -> usize {
+        match self {
+            SpectrumKind::Illuminant | SpectrumKind::Reflectance => 1,
+            SpectrumKind::Conductor => 2,
+        }
+    }
+}
+
+// A CSV file with an increasing key in the first column, like a wavelength or an altitude, followed by a fixed
+// number of values on each line. Lines starting with `#` and a header line are skipped.
+pub struct Table {
+    keys: Vec<f32>,
+    values: Vec<Vec<f32>>,
+}
+
+impl Table {
+    pub fn load(path: &Path, value_count: usize) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+
+        for (line_index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Result<Vec<f32>, _> =
+                line.split(',').map(|field| field.trim().parse()).collect();
+
+            let Ok(fields) = fields else {
+                // the header, which has to come before any data
+                if keys.is_empty() {
+                    continue;
+                }
+
+                return Err(invalid_data(
+                    path,
+                    format!("line {} isn't numeric", line_index + 1),
+                ));
+            };
+
+            if fields.len() != value_count + 1 {
+                return Err(invalid_data(
+                    path,
+                    format!(
+                        "line {} has {} columns, expected {}",
+                        line_index + 1,
+                        fields.len(),
+                        value_count + 1
+                    ),
+                ));
+            }
+
+            if keys.last().is_some_and(|&last| fields[0] <= last) {
+                return Err(invalid_data(
+                    path,
+                    format!("keys aren't increasing at line {}", line_index + 1),
+                ));
+            }
+
+            keys.push(fields[0]);
+            values.push(fields[1..].to_vec());
+        }
+
+        if keys.len() < 2 {
+            return Err(invalid_data(path, "needs at least two rows".to_owned()));
+        }
+
+        Ok(Self { keys, values })
+    }
+
+    pub fn last_key(&self) -> f32 {
+        self.keys[self.keys.len() - 1]
+    }
+
+    // Linear interpolation, keys outside of the tabulated range are clamped to the closest row
+    pub fn sample(&self, key: f32, column: usize) -> f32 {
+        let end = self
+            .keys
+            .partition_point(|&value| value < key)
+            .clamp(1, self.keys.len() - 1);
+        let start = end - 1;
+
+        let t = ((key - self.keys[start]) / (self.keys[end] - self.keys[start])).clamp(0.0, 1.0);
+
+        self.values[start][column] + (self.values[end][column] - self.values[start][column]) * t
+    }
+}
+
+pub fn invalid_data(path: &Path, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+pub struct TabulatedSpectrum {
+    // File name without the extension
+    pub name: String,
+    pub kind: SpectrumKind,
+
+    // Resampled to the atlas wavelengths, the second value is only used by conductors
+    samples: Vec<[f32; 2]>,
+}
+
+impl TabulatedSpectrum {
+    // Reads a CSV file with a wavelength in nm followed by the value(s) of the spectrum on each line
+    fn load(path: &Path, kind: SpectrumKind) -> io::Result<Self> {
+        let table = Table::load(path, kind.column_count())?;
+
+        let mut samples: Vec<[f32; 2]> = (0..SPECTRAL_ATLAS_WIDTH)
+            .map(|index| {
+                let wavelength = SPECTRAL_ATLAS_START + index as f32;
+
+                [0, 1].map(|column| {
+                    if column < kind.column_count() {
+                        table.sample(wavelength, column)
+                    } else {
+                        0.0
+                    }
+                })
+            })
+            .collect();
+
+        if kind == SpectrumKind::Illuminant {
+            let index = (ILLUMINANT_NORMALIZATION_WAVELENGTH - SPECTRAL_ATLAS_START) as usize;
+            let scale = samples[index][0];
+
+            if scale <= 0.0 {
+                return Err(invalid_data(
+                    path,
+                    format!(
+                        "illuminant has no power at {}nm",
+                        ILLUMINANT_NORMALIZATION_WAVELENGTH
+                    ),
+                ));
+            }
+
+            for sample in &mut samples {
+                sample[0] /= scale;
+            }
+        }
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(Self {
+            name,
+            kind,
+            samples,
+        })
+    }
+}
+
+/// Tabulated spectra loaded from the CSV files in assets/spectra, packed into one texture with a row per spectrum.
+/// The first row is left empty, so index 0 stands for no spectrum, like the null elements of the object buffers.
+pub struct SpectralAtlas {
+    pub spectra: Vec<TabulatedSpectrum>,
+}
+
+impl SpectralAtlas {
+    pub fn load() -> Self {
+        let mut spectra = Vec::new();
+
+        for kind in SpectrumKind::ALL {
+            let directory = util::asset_path("spectra").join(kind.directory());
+
+            // a kind without any data is fine
+            let Ok(entries) = fs::read_dir(&directory) else {
+                continue;
+            };
+
+            let mut paths: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|extension| extension == "csv"))
+                .collect();
+
+            // keep indices stable between runs
+            paths.sort();
+
+            for path in paths {
+                match TabulatedSpectrum::load(&path, kind) {
+                    Ok(spectrum) => spectra.push(spectrum),
+                    Err(err) => log::error!("Failed to load spectrum: {}", err),
+                }
+            }
+        }
+
+        Self { spectra }
+    }
+
+    pub fn name(&self, index: u32) -> Option<&str> {
+        index
+            .checked_sub(1)
+            .and_then(|position| self.spectra.get(position as usize))
+            .map(|spectrum| spectrum.name.as_str())
+    }
+
+    // Spectra of a kind along with their index in the atlas texture, which is what materials reference them by
+    pub fn of_kind(&self, kind: SpectrumKind) -> impl Iterator<Item = (u32, &TabulatedSpectrum)> {
+        self.spectra
+            .iter()
+            .enumerate()
+            .filter(move |(_, spectrum)| spectrum.kind == kind)
+            .map(|(position, spectrum)| (position as u32 + 1, spectrum))
+    }
+
+    pub fn height(&self) -> u32 {
+        self.spectra.len() as u32 + 1
+    }
+
+    // Rg32Float texels of the atlas texture
+    pub fn texels(&self) -> Vec<f32> {
+        let empty_row = vec![[0.0; 2]; SPECTRAL_ATLAS_WIDTH as usize];
+
+        std::iter::once(&empty_row)
+            .chain(self.spectra.iter().map(|spectrum| &spectrum.samples))
+            .flatten()
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every kind of spectrum the atlas supports has data in assets/spectra, and all of it loads with sensible values
+    #[test]
+    fn every_kind_loads() {
+        for kind in SpectrumKind::ALL {
+            let directory = util::asset_path("spectra").join(kind.directory());
+
+            let paths: Vec<_> = fs::read_dir(&directory)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "csv"))
+                .collect();
+
+            assert!(
+                !paths.is_empty(),
+                "no {:?} spectra in {}",
+                kind,
+                directory.display()
+            );
+
+            for path in paths {
+                let spectrum =
+                    TabulatedSpectrum::load(&path, kind).unwrap_or_else(|err| panic!("{}", err));
+
+                for &[value, k] in &spectrum.samples {
+                    match kind {
+                        SpectrumKind::Illuminant => assert!(value >= 0.0, "{}", spectrum.name),
+                        SpectrumKind::Reflectance => {
+                            assert!((0.0..=1.0).contains(&value), "{}", spectrum.name)
+                        }
+                        SpectrumKind::Conductor => {
+                            assert!(value > 0.0 && k >= 0.0, "{}", spectrum.name)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+/*
+***Density grid converter***
+
+Converts a raw dump of voxel densities into the .grid format the renderer loads from assets/volumes, which is
+documented in src/app/object/density_grid.rs. A raw dump holds the voxels one after the other without a header, with
+x changing fastest and z slowest.
+
+Densities are rescaled so the densest voxel has a density of 1, the density scale of the medium sets the actual
+density in the renderer.
+
+Run from the repository root:
+    cargo run --release --bin density_grid -- <input> <width> <height> <depth> <u8|u16|f32> [options]
+
+Options:
+    --voxel-size <size>     size of a voxel in world units, 0.01 by default
+    --output <path>         where to write the grid, assets/volumes/<input name>.grid by default
+*/
+
+use std::{env, fs, path::PathBuf, process::ExitCode};
+
+// Matches density_grid.rs
+const GRID_MAGIC: &[u8; 4] = b"GRID";
+const GRID_VERSION: u32 = 1;
+
+const DEFAULT_VOXEL_SIZE: f32 = 0.01;
+
+#[derive(Clone, Copy)]
+enum VoxelFormat {
+    U8,
+    // Little-endian, like the f32s
+    U16,
+    F32,
+}
+
+impl VoxelFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "u8" => Some(VoxelFormat::U8),
+            "u16" => Some(VoxelFormat::U16),
+            "f32" => Some(VoxelFormat::F32),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            VoxelFormat::U8 => 1,
+            VoxelFormat::U16 => 2,
+            VoxelFormat::F32 => 4,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            VoxelFormat::U8 => bytes[0] as f32,
+            VoxelFormat::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+            VoxelFormat::F32 => f32::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+}
+
+struct Arguments {
+    input: PathBuf,
+    size: [u32; 3],
+    format: VoxelFormat,
+    voxel_size: f32,
+    output: PathBuf,
+}
+
+impl Arguments {
+    fn parse() -> Result<Self, String> {
+        let mut args = env::args().skip(1);
+        let mut positional = Vec::new();
+
+        let mut voxel_size = DEFAULT_VOXEL_SIZE;
+        let mut output = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--voxel-size" => {
+                    voxel_size = args
+                        .next()
+                        .and_then(|value| value.parse().ok())
+                        .filter(|&value: &f32| value > 0.0)
+                        .ok_or("--voxel-size needs a size above 0")?;
+                }
+                "--output" => {
+                    output = Some(PathBuf::from(args.next().ok_or("--output needs a path")?));
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        let [input, width, height, depth, format] = positional.as_slice() else {
+            return Err("Expected <input> <width> <height> <depth> <u8|u16|f32>".to_owned());
+        };
+
+        let parse_dimension = |value: &String| {
+            value
+                .parse()
+                .ok()
+                .filter(|&value: &u32| value > 0)
+                .ok_or(format!("{} isn't a valid grid dimension", value))
+        };
+
+        let size = [
+            parse_dimension(width)?,
+            parse_dimension(height)?,
+            parse_dimension(depth)?,
+        ];
+
+        let format =
+            VoxelFormat::parse(format).ok_or(format!("Unknown voxel format {}", format))?;
+
+        let input = PathBuf::from(input);
+
+        let output = output.unwrap_or_else(|| {
+            let name = input.file_stem().unwrap_or_default().to_string_lossy();
+
+            env::current_dir()
+                .unwrap()
+                .join("assets/volumes")
+                .join(format!("{}.grid", name))
+        });
+
+        Ok(Self {
+            input,
+            size,
+            format,
+            voxel_size,
+            output,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let arguments = match Arguments::parse() {
+        Ok(arguments) => arguments,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let bytes = match fs::read(&arguments.input) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("Failed to read {}: {}", arguments.input.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let [width, height, depth] = arguments.size;
+    let voxel_count = width as usize * height as usize * depth as usize;
+
+    if bytes.len() != voxel_count * arguments.format.size() {
+        eprintln!(
+            "{} is {} bytes, but a {}x{}x{} grid of that format is {} bytes",
+            arguments.input.display(),
+            bytes.len(),
+            width,
+            height,
+            depth,
+            voxel_count * arguments.format.size()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let mut densities: Vec<f32> = bytes
+        .chunks_exact(arguments.format.size())
+        .map(|voxel| arguments.format.decode(voxel))
+        .map(|density| {
+            if density.is_finite() {
+                density.max(0.0)
+            } else {
+                0.0
+            }
+        })
+        .collect();
+
+    let max_density = densities.iter().copied().fold(0.0, f32::max);
+
+    if max_density <= 0.0 {
+        eprintln!("{} is empty", arguments.input.display());
+        return ExitCode::FAILURE;
+    }
+
+    for density in &mut densities {
+        *density /= max_density;
+    }
+
+    let mut grid = Vec::with_capacity(24 + voxel_count * 4);
+    grid.extend_from_slice(GRID_MAGIC);
+    grid.extend_from_slice(&GRID_VERSION.to_le_bytes());
+
+    for dimension in arguments.size {
+        grid.extend_from_slice(&dimension.to_le_bytes());
+    }
+
+    grid.extend_from_slice(&arguments.voxel_size.to_le_bytes());
+    grid.extend(densities.iter().flat_map(|density| density.to_le_bytes()));
+
+    if let Some(directory) = arguments.output.parent() {
+        if let Err(err) = fs::create_dir_all(directory) {
+            eprintln!("Failed to create {}: {}", directory.display(), err);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Err(err) = fs::write(&arguments.output, grid) {
+        eprintln!("Failed to write {}: {}", arguments.output.display(), err);
+        return ExitCode::FAILURE;
+    }
+
+    println!(
+        "Wrote a {}x{}x{} grid to {}",
+        width,
+        height,
+        depth,
+        arguments.output.display()
+    );
+
+    ExitCode::SUCCESS
+}
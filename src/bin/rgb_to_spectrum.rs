@@ -0,0 +1,616 @@
+/*
+***RGB to spectrum coefficient table***
+
+Generates the table `Spectrum` uses to turn sRGB albedos into smooth reflectance spectra, following
+"A Low-Dimensional Function Space for Efficient Spectral Upsampling" (Jakob & Hanika 2019):
+https://rgl.epfl.ch/publications/Jakob2019Spectral
+
+Every spectrum is a sigmoid of a quadratic polynomial of the wavelength, so it's smooth and always within [0, 1].
+The polynomial coefficients are found with Gauss-Newton iterations for a grid of colors, parametrized by the largest
+component of the color and the ratios of the other two components to it.
+
+Colors are integrated with the CIE color matching functions of wavelength_to_xyz.bin over their whole range, 360 to
+830nm, under an equal energy illuminant. The result is white balanced so a spectrum of 1 maps to white. This is not
+quite what the renderer does: it only samples wavelengths from 380 to 780nm and doesn't white balance, so the colors
+it renders can differ slightly from the ones the table was fitted to.
+
+Run from the repository root:
+    cargo run --release --bin rgb_to_spectrum
+    cargo run --release --bin rgb_to_spectrum -- --verify
+
+Without arguments the table is generated, verified and written to assets/textures/spectrum. With `--verify`, the
+existing table is only verified, by round tripping colors through it the same way the shader does.
+*/
+
+use std::{env, fs, io, path::PathBuf, process::ExitCode};
+
+// Matches `RGB_TO_SPECTRUM_RESOLUTION` in spectrum.slang and lookup.rs
+const RESOLUTION: usize = 32;
+
+// wavelength_to_xyz.bin holds Rgba32Float texels, from 360nm to 830nm in 1nm steps
+const CMF_START: f64 = 360.0;
+const CMF_END: f64 = 830.0;
+const CMF_SAMPLES: usize = 471;
+
+const MAX_ITERATIONS: usize = 50;
+
+// Largest allowed round trip error of any color component
+const TOLERANCE: f64 = 0.01;
+
+// `xyzToRgb` in header.slang
+const XYZ_TO_RGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+type Vec3 = [f64; 3];
+type Mat3 = [[f64; 3]; 3];
+
+fn mul(matrix: &Mat3, vector: Vec3) -> Vec3 {
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+fn inverse(m: &Mat3) -> Mat3 {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+
+    let adjugate = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+        ],
+        [
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+        ],
+        [
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+
+    let determinant =
+        m[0][0] * adjugate[0][0] + m[0][1] * adjugate[1][0] + m[0][2] * adjugate[2][0];
+
+    adjugate.map(|row| row.map(|value| value / determinant))
+}
+
+// Solves `matrix * x = vector` with gaussian elimination, None if the matrix is singular
+fn solve(mut matrix: Mat3, mut vector: Vec3) -> Option<Vec3> {
+    for column in 0..3 {
+        let pivot = (column..3)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))
+            .unwrap();
+
+        if matrix[pivot][column].abs() < 1e-15 {
+            return None;
+        }
+
+        matrix.swap(column, pivot);
+        vector.swap(column, pivot);
+
+        let pivot_row = matrix[column];
+
+        for row in column + 1..3 {
+            let factor = matrix[row][column] / pivot_row[column];
+
+            for (value, pivot_value) in matrix[row].iter_mut().zip(pivot_row).skip(column) {
+                *value -= factor * pivot_value;
+            }
+
+            vector[row] -= factor * vector[column];
+        }
+    }
+
+    let mut x = [0.0; 3];
+
+    for row in (0..3).rev() {
+        let sum: f64 = (row + 1..3).map(|k| matrix[row][k] * x[k]).sum();
+        x[row] = (vector[row] - sum) / matrix[row][row];
+    }
+
+    Some(x)
+}
+
+fn sigmoid(x: f64) -> f64 {
+    0.5 + x / (2.0 * (1.0 + x * x).sqrt())
+}
+
+fn smoothstep(x: f64) -> f64 {
+    x * x * (3.0 - 2.0 * x)
+}
+
+// Values of the largest color component the table is built for, denser towards 0 and 1
+fn scale() -> Vec<f64> {
+    (0..RESOLUTION)
+        .map(|k| smoothstep(smoothstep(k as f64 / (RESOLUTION - 1) as f64)))
+        .collect()
+}
+
+struct Observer {
+    // Wavelengths of the samples, normalized to [0, 1]
+    wavelengths: Vec<f64>,
+
+    // Contribution of each wavelength sample to the white balanced RGB color, including the integration weight
+    rgb_weights: Vec<Vec3>,
+
+    rgb_to_xyz: Mat3,
+    white_xyz: Vec3,
+}
+
+impl Observer {
+    fn load(path: &PathBuf) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() != CMF_SAMPLES * 16 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} doesn't hold {} texels", path.display(), CMF_SAMPLES),
+            ));
+        }
+
+        let cmfs: Vec<Vec3> = bytes
+            .chunks_exact(16)
+            .map(|texel| {
+                let channel = |index: usize| {
+                    f32::from_le_bytes(texel[index * 4..index * 4 + 4].try_into().unwrap()) as f64
+                };
+
+                [channel(0), channel(1), channel(2)]
+            })
+            .collect();
+
+        // trapezoidal integration over the 1nm steps
+        let integration_weight = |index: usize| {
+            if index == 0 || index == CMF_SAMPLES - 1 {
+                0.5
+            } else {
+                1.0
+            }
+        };
+
+        let unbalanced: Vec<Vec3> = cmfs
+            .iter()
+            .enumerate()
+            .map(|(index, &xyz)| {
+                mul(
+                    &XYZ_TO_RGB,
+                    xyz.map(|value| value * integration_weight(index)),
+                )
+            })
+            .collect();
+
+        // color of a spectrum of 1, which should come out white
+        let white = unbalanced.iter().fold([0.0; 3], |sum, rgb| {
+            [sum[0] + rgb[0], sum[1] + rgb[1], sum[2] + rgb[2]]
+        });
+
+        let rgb_weights = unbalanced
+            .iter()
+            .map(|rgb| [rgb[0] / white[0], rgb[1] / white[1], rgb[2] / white[2]])
+            .collect();
+
+        let wavelengths = (0..CMF_SAMPLES)
+            .map(|index| index as f64 / (CMF_SAMPLES - 1) as f64)
+            .collect();
+
+        let rgb_to_xyz = inverse(&XYZ_TO_RGB);
+        let white_xyz = mul(&rgb_to_xyz, [1.0; 3]);
+
+        Ok(Self {
+            wavelengths,
+            rgb_weights,
+            rgb_to_xyz,
+            white_xyz,
+        })
+    }
+
+    // Color of the spectrum with the given coefficients, for normalized wavelengths
+    fn rgb(&self, coefficients: Vec3) -> Vec3 {
+        let mut rgb = [0.0; 3];
+
+        for (&wavelength, weight) in self.wavelengths.iter().zip(&self.rgb_weights) {
+            let x = (coefficients[0] * wavelength + coefficients[1]) * wavelength + coefficients[2];
+            let spectrum = sigmoid(x);
+
+            for channel in 0..3 {
+                rgb[channel] += spectrum * weight[channel];
+            }
+        }
+
+        rgb
+    }
+
+    // The residual is measured in CIELAB, so the error is spread out evenly in perceptual terms
+    fn lab(&self, rgb: Vec3) -> Vec3 {
+        let xyz = mul(&self.rgb_to_xyz, rgb);
+
+        let f = |t: f64| {
+            let delta: f64 = 6.0 / 29.0;
+
+            if t > delta.powi(3) {
+                t.cbrt()
+            } else {
+                t / (3.0 * delta * delta) + 4.0 / 29.0
+            }
+        };
+
+        let [x, y, z] = [0, 1, 2].map(|index| f(xyz[index] / self.white_xyz[index]));
+
+        [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+    }
+
+    fn residual(&self, coefficients: Vec3, target: Vec3) -> Vec3 {
+        let lab = self.lab(self.rgb(coefficients));
+        let target_lab = self.lab(target);
+
+        [0, 1, 2].map(|index| target_lab[index] - lab[index])
+    }
+
+    fn gauss_newton(&self, target: Vec3, coefficients: &mut Vec3) {
+        for _ in 0..MAX_ITERATIONS {
+            let residual = self.residual(*coefficients, target);
+
+            // jacobian of the residual by central differences
+            let mut jacobian = [[0.0; 3]; 3];
+            let epsilon = 1e-5;
+
+            for column in 0..3 {
+                let mut forward = *coefficients;
+                let mut backward = *coefficients;
+
+                forward[column] += epsilon;
+                backward[column] -= epsilon;
+
+                let forward = self.residual(forward, target);
+                let backward = self.residual(backward, target);
+
+                for row in 0..3 {
+                    jacobian[row][column] = (forward[row] - backward[row]) / (2.0 * epsilon);
+                }
+            }
+
+            let Some(step) = solve(jacobian, residual) else {
+                break;
+            };
+
+            let error = norm(residual);
+
+            if error < 1e-6 {
+                break;
+            }
+
+            // halve the step until it improves the fit, full steps can overshoot into a sigmoid so steep that the
+            // iterations never recover from it
+            let mut step_size = 1.0;
+
+            while step_size > 1e-3 {
+                let candidate =
+                    [0, 1, 2].map(|index| coefficients[index] - step[index] * step_size);
+
+                if norm(self.residual(candidate, target)) < error {
+                    *coefficients = candidate;
+                    break;
+                }
+
+                step_size *= 0.5;
+            }
+
+            if step_size <= 1e-3 {
+                break;
+            }
+        }
+    }
+}
+
+fn norm(vector: Vec3) -> f64 {
+    vector.iter().map(|value| value * value).sum::<f64>().sqrt()
+}
+
+// Coefficients for normalized wavelengths to coefficients for wavelengths in nanometers
+fn to_nanometers(coefficients: Vec3) -> Vec3 {
+    let [a, b, c] = coefficients;
+
+    let offset = CMF_START;
+    let scale = 1.0 / (CMF_END - CMF_START);
+
+    [
+        a * scale * scale,
+        b * scale - 2.0 * a * offset * scale * scale,
+        c - b * offset * scale + a * (offset * scale).powi(2),
+    ]
+}
+
+// Coefficients for every color component being the largest, then the largest component's value, then the ratios
+// of the other two components. Laid out like the 3D texture it's loaded into, with the x ratio changing fastest.
+struct Table {
+    scale: Vec<f64>,
+    coefficients: Vec<Vec3>,
+}
+
+impl Table {
+    fn index(component: usize, k: usize, j: usize, i: usize) -> usize {
+        ((component * RESOLUTION + k) * RESOLUTION + j) * RESOLUTION + i
+    }
+
+    fn generate(observer: &Observer) -> Self {
+        let scale = scale();
+        let mut coefficients = vec![[0.0; 3]; 3 * RESOLUTION.pow(3)];
+
+        for component in 0..3 {
+            println!("Optimizing component {} of 3", component + 1);
+
+            for j in 0..RESOLUTION {
+                let y = j as f64 / (RESOLUTION - 1) as f64;
+
+                for i in 0..RESOLUTION {
+                    let x = i as f64 / (RESOLUTION - 1) as f64;
+
+                    // Starts from a mid brightness and works outwards, each solution being the initial guess for
+                    // the next one. Very dark and very bright colors don't converge from scratch.
+                    let start = RESOLUTION / 5;
+
+                    let mut solve_range = |range: &mut dyn Iterator<Item = usize>| {
+                        let mut guess = [0.0; 3];
+
+                        for k in range {
+                            let z = scale[k];
+
+                            let mut rgb = [0.0; 3];
+                            rgb[component] = z;
+                            rgb[(component + 1) % 3] = x * z;
+                            rgb[(component + 2) % 3] = y * z;
+
+                            observer.gauss_newton(rgb, &mut guess);
+                            coefficients[Self::index(component, k, j, i)] = to_nanometers(guess);
+                        }
+                    };
+
+                    solve_range(&mut (start..RESOLUTION));
+                    solve_range(&mut (0..=start).rev());
+                }
+            }
+        }
+
+        Self {
+            scale,
+            coefficients,
+        }
+    }
+
+    fn load(scale_path: &PathBuf, coefficients_path: &PathBuf) -> io::Result<Self> {
+        let read_floats = |path: &PathBuf| -> io::Result<Vec<f64>> {
+            Ok(fs::read(path)?
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()) as f64)
+                .collect())
+        };
+
+        let scale = read_floats(scale_path)?;
+        let coefficients: Vec<Vec3> = read_floats(coefficients_path)?
+            .chunks_exact(4)
+            .map(|texel| [texel[0], texel[1], texel[2]])
+            .collect();
+
+        if scale.len() != RESOLUTION || coefficients.len() != 3 * RESOLUTION.pow(3) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The table doesn't have a resolution of {}", RESOLUTION),
+            ));
+        }
+
+        Ok(Self {
+            scale,
+            coefficients,
+        })
+    }
+
+    // Same as `Spectrum::rgbToSpectralReflectance` in spectrum.slang, except it returns the coefficients. The table is
+    // stored as 32-bit floats, so the lookup is done in 32-bit floats as well.
+    fn lookup(&self, rgb: Vec3) -> Vec3 {
+        // ties go to the later component, like in the shader
+        let component = if rgb[0] > rgb[1] {
+            if rgb[0] > rgb[2] {
+                0
+            } else {
+                2
+            }
+        } else if rgb[1] > rgb[2] {
+            1
+        } else {
+            2
+        };
+
+        let z = rgb[component];
+        let resolution = (RESOLUTION - 1) as f64;
+
+        let x = rgb[(component + 1) % 3] / z * resolution;
+        let y = rgb[(component + 2) % 3] / z * resolution;
+
+        let xi = (x as usize).min(RESOLUTION - 2);
+        let yi = (y as usize).min(RESOLUTION - 2);
+        let zi = self.scale[1..RESOLUTION - 1]
+            .iter()
+            .take_while(|&&value| value < z)
+            .count();
+
+        let dx = x - xi as f64;
+        let dy = y - yi as f64;
+        let dz = (z - self.scale[zi]) / (self.scale[zi + 1] - self.scale[zi]);
+
+        let mut coefficients = [0.0; 3];
+
+        for (k, wz) in [(zi, 1.0 - dz), (zi + 1, dz)] {
+            for (j, wy) in [(yi, 1.0 - dy), (yi + 1, dy)] {
+                for (i, wx) in [(xi, 1.0 - dx), (xi + 1, dx)] {
+                    let texel = self.coefficients[Self::index(component, k, j, i)];
+
+                    for index in 0..3 {
+                        coefficients[index] += wx * wy * wz * (texel[index] as f32) as f64;
+                    }
+                }
+            }
+        }
+
+        coefficients
+    }
+
+    fn write(&self, scale_path: &PathBuf, coefficients_path: &PathBuf) -> io::Result<()> {
+        let scale: Vec<u8> = self
+            .scale
+            .iter()
+            .flat_map(|&value| (value as f32).to_le_bytes())
+            .collect();
+
+        // Rgba32Float texels, the alpha channel is unused
+        let coefficients: Vec<u8> = self
+            .coefficients
+            .iter()
+            .flat_map(|texel| [texel[0], texel[1], texel[2], 0.0])
+            .flat_map(|value| (value as f32).to_le_bytes())
+            .collect();
+
+        fs::write(scale_path, scale)?;
+        fs::write(coefficients_path, coefficients)
+    }
+}
+
+// Round trips a grid of colors through the table, and returns the largest and average error of any component
+fn verify(observer: &Observer, table: &Table) -> (f64, f64, Vec3) {
+    const STEPS: usize = 24;
+
+    let mut max_error = 0.0;
+    let mut worst = [0.0; 3];
+    let mut total_error = 0.0;
+    let mut count = 0;
+
+    for b in 0..=STEPS {
+        for g in 0..=STEPS {
+            for r in 0..=STEPS {
+                let rgb = [r, g, b].map(|value| value as f64 / STEPS as f64);
+
+                // grays are a constant spectrum in the shader, which round trips exactly
+                if rgb[0] == rgb[1] && rgb[1] == rgb[2] {
+                    continue;
+                }
+
+                // from nanometers back to normalized wavelengths, for `Observer::rgb`
+                let [a, b, c] = table.lookup(rgb);
+                let span = CMF_END - CMF_START;
+                let normalized = [
+                    a * span * span,
+                    b * span + 2.0 * a * CMF_START * span,
+                    c + b * CMF_START + a * CMF_START * CMF_START,
+                ];
+
+                let round_trip = observer.rgb(normalized);
+
+                let error = (0..3)
+                    .map(|index| (round_trip[index] - rgb[index]).abs())
+                    .fold(0.0, f64::max);
+
+                if error > max_error {
+                    max_error = error;
+                    worst = rgb;
+                }
+
+                total_error += error;
+                count += 1;
+            }
+        }
+    }
+
+    (max_error, total_error / count as f64, worst)
+}
+
+fn main() -> ExitCode {
+    let spectrum_dir = env::current_dir().unwrap().join("assets/textures/spectrum");
+
+    let cmf_path = spectrum_dir.join("wavelength_to_xyz.bin");
+    let scale_path = spectrum_dir.join("rgb_to_spectrum_scale.bin");
+    let coefficients_path = spectrum_dir.join("rgb_to_spectrum_coefficients.bin");
+
+    let observer = match Observer::load(&cmf_path) {
+        Ok(observer) => observer,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", cmf_path.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let verify_only = env::args().any(|arg| arg == "--verify");
+
+    let table = if verify_only {
+        match Table::load(&scale_path, &coefficients_path) {
+            Ok(table) => table,
+            Err(err) => {
+                eprintln!("Failed to load the table: {}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        Table::generate(&observer)
+    };
+
+    let (max_error, average_error, worst) = verify(&observer, &table);
+
+    println!(
+        "Round trip error: max {:.5} at {:.3?}, average {:.6}",
+        max_error, worst, average_error
+    );
+
+    if max_error > TOLERANCE {
+        eprintln!("Round trip error is above the tolerance of {}", TOLERANCE);
+        return ExitCode::FAILURE;
+    }
+
+    if !verify_only {
+        if let Err(err) = table.write(&scale_path, &coefficients_path) {
+            eprintln!("Failed to write the table: {}", err);
+            return ExitCode::FAILURE;
+        }
+
+        println!(
+            "Wrote {} and {}",
+            scale_path.display(),
+            coefficients_path.display()
+        );
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spectrum_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/textures/spectrum")
+    }
+
+    #[test]
+    fn committed_table_round_trips_within_tolerance() {
+        let spectrum_dir = spectrum_dir();
+
+        let observer = Observer::load(&spectrum_dir.join("wavelength_to_xyz.bin")).unwrap();
+        let table = Table::load(
+            &spectrum_dir.join("rgb_to_spectrum_scale.bin"),
+            &spectrum_dir.join("rgb_to_spectrum_coefficients.bin"),
+        )
+        .unwrap();
+
+        let (max_error, _, worst) = verify(&observer, &table);
+
+        assert!(
+            max_error <= TOLERANCE,
+            "round trip error {} at {:?} is above the tolerance of {}",
+            max_error,
+            worst,
+            TOLERANCE
+        );
+    }
+}
@@ -2,8 +2,8 @@ use bevy_ecs::schedule::{IntoScheduleConfigs, Schedule, ScheduleLabel};
 
 use crate::{
     app::{
-        camera, control,
-        events::{KeyEvent, MenuResizeEvent, MouseInput, MouseMotion},
+        atmosphere, camera, control,
+        events::{ExportEvent, KeyEvent, MenuResizeEvent, MouseInput, MouseMotion},
         fps, input, lookup, menu, object,
         renderer::{self, profiler},
         time,
@@ -98,6 +98,7 @@ impl Default for Schedules {
 
         schedules.on_init_event_setup.add_systems((
             event::init::<MenuResizeEvent>,
+            event::init::<ExportEvent>,
             event::init::<MouseMotion>,
             event::init::<KeyEvent>,
             event::init::<MouseInput>,
@@ -107,14 +108,22 @@ impl Default for Schedules {
             (
                 (
                     renderer::RendererViewport::init,
+                    renderer::RenderResolution::init,
                     lookup::SpectrumBinding::init,
                     lookup::CameraResponseBinding::init,
                     object::binding::ObjectBinding::init,
+                    atmosphere::binding::AtmosphereBinding::init,
                     camera::binding::ScreenBinding::init,
                     renderer::profiler::RenderProfiler::init,
+                    renderer::accumulation::Accumulation::init,
+                    renderer::export::Export::init,
                 ),
+                renderer::graph::RenderGraph::init,
                 renderer::material::MaterialTextures::init,
                 renderer::material::MaterialPipelines::init,
+                renderer::atmosphere_lut::AtmosphereLutPipelines::init,
+                renderer::denoise::DenoiseBinding::init,
+                renderer::denoise::DenoisePipelines::init,
                 renderer::display::DisplayBinding::init,
                 renderer::display::DisplayPipelines::init,
             )
@@ -125,6 +134,7 @@ impl Default for Schedules {
             fps::FpsCounter::init,
             menu::Menu::init,
             object::Objects::init,
+            atmosphere::Atmosphere::init,
             camera::Camera::init,
         ));
 
@@ -142,10 +152,13 @@ impl Default for Schedules {
             (
                 menu::Menu::update,
                 object::binding::ObjectBinding::update,
+                atmosphere::binding::AtmosphereBinding::update,
                 camera::binding::ScreenBinding::update,
             ),
-            renderer::material::draw,
-            renderer::display::draw,
+            renderer::material::MaterialPipelines::update,
+            renderer::accumulation::Accumulation::update,
+            renderer::graph::RenderGraph::draw,
+            renderer::export::Export::record,
         )
             .chain(),));
 
@@ -155,6 +168,8 @@ impl Default for Schedules {
                     input::update_system,
                     time::update_system,
                     profiler::RenderProfiler::post_render,
+                    renderer::accumulation::Accumulation::post_render,
+                    renderer::export::Export::post_render,
                 ),
                 // Run the fps update after EVERYTHING is done
                 bevy_ecs::prelude::ApplyDeferred,
@@ -165,19 +180,23 @@ impl Default for Schedules {
 
         schedules.on_redraw_event_update.add_systems((
             event::update::<MenuResizeEvent>,
+            event::update::<ExportEvent>,
             event::update::<MouseMotion>,
             event::update::<KeyEvent>,
             event::update::<MouseInput>,
         ));
 
-        schedules.on_resize.add_systems((
-            camera::Camera::on_resize,
+        schedules.on_resize.add_systems(
             (
-                renderer::material::MaterialTextures::on_resize,
-                renderer::display::DisplayBinding::on_resize,
+                renderer::RenderResolution::on_resize,
+                (
+                    camera::Camera::on_resize,
+                    renderer::graph::RenderGraph::on_resize,
+                    renderer::display::DisplayBinding::on_resize,
+                ),
             )
                 .chain(),
-        ));
+        );
 
         schedules
     }
@@ -8,6 +8,7 @@ mod util;
 pub const WGPU_FEATURES: wgpu::Features = wgpu::Features::FLOAT32_FILTERABLE
     .union(wgpu::Features::RG11B10UFLOAT_RENDERABLE)
     .union(wgpu::Features::TEXTURE_BINDING_ARRAY)
+    .union(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING)
     .union(wgpu::Features::PUSH_CONSTANTS)
     .union(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER)
     .union(wgpu::Features::ADDRESS_MODE_CLAMP_TO_ZERO)
@@ -21,6 +22,9 @@ pub const WGPU_FEATURES: wgpu::Features = wgpu::Features::FLOAT32_FILTERABLE
 pub fn wgpu_limits() -> wgpu::Limits {
     wgpu::Limits {
         max_push_constant_size: 128,
+        // Material textures are bound as two arrays
+        max_binding_array_elements_per_shader_stage: 2 * app::object::texture::MAX_IMAGE_TEXTURES
+            as u32,
         ..Default::default()
     }
 }